@@ -2,6 +2,7 @@ package main
 
 import (
 	"encoding/json"
+	"io/ioutil"
 	"os"
 	"log"
 	"net"
@@ -15,18 +16,43 @@ type FunctionPayload struct {
 }
 
 type FunctionRequest struct {
+	Function string `json:"function"`
 	Path string `json:"path"`
 	Method string `json:"method"`
-	Headers map[string]string `json:"headers"`
+	Headers map[string][]string `json:"headers"`
+	Cookies map[string]string `json:"cookies"`
 	QueryString string `json:"query_string"`
+	Query map[string][]string `json:"query"`
 	Script string `json:"script"`
 	Body string `json:"body"`
+	IsBase64Encoded bool `json:"is_base64_encoded"`
+	Route string `json:"route"`
+	Params map[string]string `json:"params"`
+	RequestId string `json:"request_id"`
+	RemoteAddr *string `json:"remote_addr"`
+	Version string `json:"version"`
+	Scheme string `json:"scheme"`
+	Host string `json:"host"`
 }
 
 type FunctionResponse struct {
 	StatusCode uint16 `json:"status_code"`
 	Body string `json:"body"`
-	Headers map[string]string `json:"headers"`
+	IsBase64Encoded bool `json:"is_base64_encoded"`
+	Headers map[string][]string `json:"headers"`
+	Cookies []Cookie `json:"cookies"`
+}
+
+type Cookie struct {
+	Name string `json:"name"`
+	Value string `json:"value"`
+	Path *string `json:"path,omitempty"`
+	Domain *string `json:"domain,omitempty"`
+	Expires *string `json:"expires,omitempty"`
+	MaxAge *int64 `json:"max_age,omitempty"`
+	Secure bool `json:"secure"`
+	HttpOnly bool `json:"http_only"`
+	SameSite *string `json:"same_site,omitempty"`
 }
 
 func main() {
@@ -65,33 +91,36 @@ func connect(addr string) {
 }
 
 func handleRequest(c net.Conn) {
-	buf := make([]byte, 1024)
-	bytesRead, err := c.Read(buf)
+	// closing the connection tells the gateway the whole response has been written
+	defer c.Close()
+
+	// the gateway shuts down its side of the connection once it has written the whole request
+	data, err := ioutil.ReadAll(c)
 
 	if err != nil {
 		log.Print("Error while reading bytes from socket: ", err)
 		return
 	}
 
-	data := buf[0:bytesRead]
-
 	payload := FunctionPayload{}
 
 	err = json.Unmarshal(data, &payload)
 
 	if err != nil {
 		log.Println("Failed to unmarshal the incoming request: ", err)
-		_ = c.Close()
 		return
 	}
 
 	payload.Res.Body = "hello from go"
 
+	if names, ok := payload.Req.Query["name"]; ok && len(names) > 0 {
+		payload.Res.Body = "hello " + names[0] + " from go"
+	}
+
 	res, err := json.Marshal(&payload.Res)
 
 	if err != nil {
 		log.Println("Failed to marshal the response: ", err)
-		_ = c.Close()
 		return
 	}
 
@@ -16,10 +16,18 @@ const parse = fastParse({
     req: {
       type: 'object',
       properties: {
+        function: { type: 'string' },
         path: { type: 'string' },
         method: { type: 'string' },
         query_string: { type: 'string' },
+        query: {
+          type: 'object',
+          properties: {
+            name: { type: 'array', items: { type: 'string' } },
+          }
+        },
         body: { type: 'string', default: '' },
+        is_base64_encoded: { type: 'boolean' },
         headers: {
           type: 'object',
           properties: {}
@@ -27,6 +35,11 @@ const parse = fastParse({
         script: {
           type: 'string',
         },
+        route: { type: 'string' },
+        request_id: { type: 'string' },
+        version: { type: 'string' },
+        scheme: { type: 'string' },
+        host: { type: 'string' },
       }
     },
     res: {
@@ -35,6 +48,9 @@ const parse = fastParse({
         body: {
           type: 'string',
         },
+        is_base64_encoded: {
+          type: 'boolean',
+        },
         status_code: {
           type: 'number'
         },
@@ -58,6 +74,9 @@ const stringify = fastStringify({
     body: {
       type: 'string',
     },
+    is_base64_encoded: {
+      type: 'boolean',
+    },
     status_code: {
       type: 'number'
     },
@@ -69,7 +88,8 @@ const stringify = fastStringify({
 });
 
 function index(req, res) {
-  res.body = 'hello';
+  const names = req.query.name || [];
+  res.body = names.length > 0 ? `hello ${names[0]}` : 'hello';
   return res;
 }
 
@@ -88,19 +108,24 @@ function handler() {
 function setup(onRequest) {
   const socket = process.argv[2];
 
-  const server = net.createServer((client) => {
+  // the gateway shuts down its side of the connection once it has written the whole request, the
+  // response is written after that so the connection has to stay half open
+  const server = net.createServer({ allowHalfOpen: true }, (client) => {
     // console.log('client connected to socket');
 
-    client.on('end', () => {
-      // console.log('client disconnected');
-    });
+    const chunks = [];
 
     client.on('data', (buf) => {
-      let json = parse(buf);
+      chunks.push(buf);
+    });
+
+    client.on('end', () => {
+      let json = parse(Buffer.concat(chunks));
 
       let res = onRequest(json.req, json.res);
 
-      client.write(stringify(res));
+      // closing the connection tells the gateway the whole response has been written
+      client.end(stringify(res));
     });
 
     client.on('error', (err) => {
@@ -13,9 +13,16 @@ pub fn handle_request(ptr: *mut i32, len: i32) -> i32 {
 
     let mut res = ctx.res;
 
-    res.body = "hello from wasm -- test".to_string();
+    res.body = match fn_api::host::env_var("GREETING") {
+        Some(greeting) => greeting.into_bytes(),
+        None => b"hello from wasm -- test".to_vec(),
+    };
 
-    res.headers.insert("x-test".to_string(), "abc".to_string());
+    if let Some(name) = ctx.req.query.get("name").and_then(|names| names.first()) {
+        res.headers.insert("x-name", name);
+    }
+
+    res.headers.insert("x-test", "abc");
 
     let res_bytes = res.to_bytes().unwrap();
 
@@ -0,0 +1,30 @@
+use crate::errors::Errors;
+use serde_json::Value;
+
+/// Bodies are serialized as raw bytes, which CBOR stores as a byte string but JSON stores as an
+/// array of numbers. For JSON the array is replaced with a string, the body itself when it is valid
+/// UTF-8 and otherwise its base64 encoding, with `is_base64_encoded` set to match.
+pub(crate) fn encode_json_body(value: &mut Value) -> Result<(), Errors> {
+    let bytes: Vec<u8> = serde_json::from_value(value["body"].take())
+        .map_err(|e| Errors::SerializationError(e.to_string()))?;
+
+    let (body, is_base64_encoded) = match String::from_utf8(bytes) {
+        Ok(body) => (body, false),
+        Err(e) => (base64::encode(e.as_bytes()), true),
+    };
+
+    value["body"] = Value::String(body);
+    value["is_base64_encoded"] = Value::Bool(is_base64_encoded);
+
+    Ok(())
+}
+
+/// Reverse of `encode_json_body`, a body read from a JSON string is the bytes of the string which
+/// need to be decoded when they are base64
+pub(crate) fn decode_json_body(body: Vec<u8>, is_base64_encoded: bool) -> Result<Vec<u8>, Errors> {
+    if is_base64_encoded {
+        base64::decode(&body).map_err(|e| Errors::DeserializationError(e.to_string()))
+    } else {
+        Ok(body)
+    }
+}
@@ -0,0 +1,74 @@
+//! The framed protocol used to stream data between the gateway and a unix socket function. Each frame
+//! is a 1 byte type, followed by the length of the payload as a big endian u32 and then the payload.
+//!
+//! A request with a streamed body is sent as a `Request` frame containing the FunctionContext as
+//! JSON, with an empty body, followed by a `Body` frame for each chunk of the body as it arrives and
+//! an empty `End` frame once the body is complete.
+//!
+//! A streamed response is sent by the function as a `Head` frame containing a FunctionResponse as
+//! JSON, whose body is ignored and can be left out, followed by a `Body` frame for each chunk of
+//! the body and an `End` frame once the response is complete.
+//!
+//! A websocket connection keeps a connection to the function open for as long as the client is
+//! connected. Each event is sent as an `Event` frame containing a WebSocketContext as JSON, and the
+//! function can send a `Text` or `Binary` frame to push a message to the client at any time, or a
+//! `Close` frame to close the connection.
+
+/// Length of the type and length that precede the payload of every frame
+pub const HEADER_LEN: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameType {
+    /// The FunctionContext of the request as JSON
+    Request = 1,
+    /// A chunk of the request's or response's body
+    Body = 2,
+    /// No more frames follow
+    End = 3,
+    /// The status, headers and cookies of a streamed response as a JSON FunctionResponse
+    Head = 4,
+    /// An event of a websocket connection as a JSON WebSocketContext
+    Event = 5,
+    /// A text message for the websocket client, must be valid UTF-8
+    Text = 6,
+    /// A binary message for the websocket client
+    Binary = 7,
+    /// Close the websocket connection
+    Close = 8,
+}
+
+impl FrameType {
+    pub fn from_u8(value: u8) -> Option<FrameType> {
+        match value {
+            1 => Some(FrameType::Request),
+            2 => Some(FrameType::Body),
+            3 => Some(FrameType::End),
+            4 => Some(FrameType::Head),
+            5 => Some(FrameType::Event),
+            6 => Some(FrameType::Text),
+            7 => Some(FrameType::Binary),
+            8 => Some(FrameType::Close),
+            _ => None,
+        }
+    }
+}
+
+/// Encode a frame, the payload must be shorter than `u32::max_value()` bytes
+pub fn encode(frame_type: FrameType, payload: &[u8]) -> Vec<u8> {
+    let len = payload.len() as u32;
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.push(frame_type as u8);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+
+    frame
+}
+
+/// Decode the type and payload length from the start of a frame, none if the type is unknown
+pub fn decode_header(header: [u8; HEADER_LEN]) -> Option<(FrameType, u32)> {
+    let frame_type = FrameType::from_u8(header[0])?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+
+    Some((frame_type, len))
+}
@@ -0,0 +1,128 @@
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Ordered multi-map of HTTP headers, a name can have any number of values. Names are stored in
+/// lowercase and kept in the order they were first added.
+///
+/// Serialized as a map of each name to the list of its values. When deserializing, a single string
+/// is accepted in place of a list.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Headers(Vec<(String, Vec<String>)>);
+
+impl Headers {
+    pub fn new() -> Headers {
+        Headers(vec![])
+    }
+
+    /// Add a value to the header, keeping any existing values
+    pub fn append(&mut self, name: &str, value: &str) {
+        let name = name.to_lowercase();
+
+        match self.0.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.push(value.to_string()),
+            None => self.0.push((name, vec![value.to_string()])),
+        }
+    }
+
+    /// Set the value of the header, replacing any existing values
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        let name = name.to_lowercase();
+        self.0.retain(|(n, _)| *n != name);
+    }
+
+    /// The first value of the header
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_all(name).first().map(|value| value.as_str())
+    }
+
+    /// Every value of the header, empty if it isn't set
+    pub fn get_all(&self, name: &str) -> &[String] {
+        let name = name.to_lowercase();
+
+        match self.0.iter().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.as_slice(),
+            None => &[],
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        !self.get_all(name).is_empty()
+    }
+
+    /// Each name and value pair, a name with multiple values is repeated
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().flat_map(|(name, values)| {
+            values
+                .iter()
+                .map(move |value| (name.as_str(), value.as_str()))
+        })
+    }
+
+    /// Number of distinct names
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Serialize for Headers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+
+        for (name, values) in self.0.iter() {
+            map.serialize_entry(name, values)?;
+        }
+
+        map.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HeaderValues {
+    One(String),
+    Many(Vec<String>),
+}
+
+struct HeadersVisitor;
+
+impl<'de> Visitor<'de> for HeadersVisitor {
+    type Value = Headers;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of header names to a value or list of values")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Headers, M::Error> {
+        let mut headers = Headers::new();
+
+        while let Some((name, values)) = access.next_entry::<String, HeaderValues>()? {
+            match values {
+                HeaderValues::One(value) => headers.append(&name, &value),
+                HeaderValues::Many(values) => {
+                    for value in values.iter() {
+                        headers.append(&name, value);
+                    }
+                }
+            }
+        }
+
+        Ok(headers)
+    }
+}
+
+impl<'de> Deserialize<'de> for Headers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Headers, D::Error> {
+        deserializer.deserialize_map(HeadersVisitor)
+    }
+}
@@ -0,0 +1,63 @@
+//! Functions provided to WebAssembly functions by the wasm runtime. Only available when compiling
+//! for wasm32.
+
+extern "C" {
+    #[link_name = "env_var"]
+    fn host_env_var(key_ptr: i32, key_len: i32, buf_ptr: i32, buf_len: i32) -> i32;
+
+    #[link_name = "ws_send"]
+    fn host_ws_send(ptr: i32, len: i32, binary: i32) -> i32;
+
+    #[link_name = "ws_close"]
+    fn host_ws_close() -> i32;
+}
+
+/// Read an environment variable from the `env` map of the function's config
+pub fn env_var(key: &str) -> Option<String> {
+    let mut buf: Vec<u8> = vec![0; 64];
+
+    loop {
+        let len = unsafe {
+            host_env_var(
+                key.as_ptr() as i32,
+                key.len() as i32,
+                buf.as_mut_ptr() as i32,
+                buf.len() as i32,
+            )
+        };
+
+        if len < 0 {
+            return None;
+        }
+
+        let len = len as usize;
+
+        // the value didn't fit, nothing was copied so try again with a buffer that is large enough
+        if len > buf.len() {
+            buf.resize(len, 0);
+            continue;
+        }
+
+        buf.truncate(len);
+
+        return String::from_utf8(buf).ok();
+    }
+}
+
+/// Push a text message to the client of the websocket connection that the current event belongs
+/// to. Returns false when not handling a websocket event
+pub fn ws_send_text(message: &str) -> bool {
+    unsafe { host_ws_send(message.as_ptr() as i32, message.len() as i32, 0) == 0 }
+}
+
+/// Push a binary message to the client of the websocket connection that the current event belongs
+/// to. Returns false when not handling a websocket event
+pub fn ws_send_binary(message: &[u8]) -> bool {
+    unsafe { host_ws_send(message.as_ptr() as i32, message.len() as i32, 1) == 0 }
+}
+
+/// Close the websocket connection that the current event belongs to. Returns false when not
+/// handling a websocket event
+pub fn ws_close() -> bool {
+    unsafe { host_ws_close() == 0 }
+}
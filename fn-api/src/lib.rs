@@ -1,6 +1,16 @@
+mod body;
+pub mod cookies;
 pub mod errors;
-
+pub mod frames;
+pub mod headers;
+#[cfg(target_arch = "wasm32")]
+pub mod host;
+pub mod websocket;
+
+use crate::body::{decode_json_body, encode_json_body};
+use crate::cookies::Cookie;
 use crate::errors::Errors;
+use crate::headers::Headers;
 use failure;
 use serde::{Deserialize, Serialize};
 use serde_json;
@@ -34,23 +44,48 @@ pub struct FunctionContext<'a> {
     pub req: FunctionRequest<'a>,
     /// Used to construct the HTTP response
     pub res: FunctionResponse,
+    /// W3C trace context of the invocation, used by the function to continue the trace
+    #[serde(default)]
+    pub traceparent: Option<String>,
 }
 
 impl<'a> FunctionContext<'a> {
     pub fn new(req: FunctionRequest<'a>, res: FunctionResponse) -> FunctionContext<'a> {
-        FunctionContext { req, res }
+        FunctionContext {
+            req,
+            res,
+            traceparent: None,
+        }
     }
 }
 
-impl<'a> ConvertFunction<'a> for FunctionContext<'a> {}
+impl<'a> ConvertFunction<'a> for FunctionContext<'a> {
+    fn to_string(&self) -> Result<String, Errors> {
+        let mut value =
+            serde_json::to_value(&self).map_err(|e| Errors::SerializationError(e.to_string()))?;
+
+        encode_json_body(&mut value["req"])?;
+        encode_json_body(&mut value["res"])?;
+
+        serde_json::to_string(&value).map_err(|e| Errors::SerializationError(e.to_string()))
+    }
+}
 
 /// If the function returns this struct, it will be used when sending the response
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FunctionResponse {
-    /// Body of the response
-    pub body: String,
-    /// Headers of the response
-    pub headers: HashMap<String, String>,
+    /// Body of the response, empty when left out
+    #[serde(with = "serde_bytes", default)]
+    pub body: Vec<u8>,
+    /// Only used with JSON, set when the body is base64 encoded because it isn't valid UTF-8
+    #[serde(default)]
+    pub is_base64_encoded: bool,
+    /// Headers of the response, none when left out
+    #[serde(default)]
+    pub headers: Headers,
+    /// Cookies to set, each is sent as a Set-Cookie header
+    #[serde(default)]
+    pub cookies: Vec<Cookie>,
     /// Http status code for the response, defaults to 200 (OK)
     #[serde(default = FunctionResponse::default_status_code())]
     pub status_code: u16,
@@ -59,8 +94,10 @@ pub struct FunctionResponse {
 impl FunctionResponse {
     pub fn new() -> FunctionResponse {
         FunctionResponse {
-            body: String::from(""),
-            headers: HashMap::new(),
+            body: vec![],
+            is_base64_encoded: false,
+            headers: Headers::new(),
+            cookies: vec![],
             status_code: 200u16,
         }
     }
@@ -71,45 +108,110 @@ impl FunctionResponse {
     }
 }
 
-impl<'a> ConvertFunction<'a> for FunctionResponse {}
+impl<'a> ConvertFunction<'a> for FunctionResponse {
+    fn to_string(&self) -> Result<String, Errors> {
+        let mut value =
+            serde_json::to_value(&self).map_err(|e| Errors::SerializationError(e.to_string()))?;
+
+        encode_json_body(&mut value)?;
+
+        serde_json::to_string(&value).map_err(|e| Errors::SerializationError(e.to_string()))
+    }
+
+    fn from_str(str: &'a str) -> Result<Self, Errors> {
+        let mut res: FunctionResponse =
+            serde_json::from_str(&str).map_err(|e| Errors::DeserializationError(e.to_string()))?;
+
+        res.body = decode_json_body(res.body, res.is_base64_encoded)?;
+        res.is_base64_encoded = false;
+
+        Ok(res)
+    }
+}
 
 // Information from the HTTP Request that is forwarded to the function
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FunctionRequest<'a> {
+    /// Name of the function that handles the request
+    pub function: &'a str,
     /// The target path of the request
     pub path: &'a str,
     /// Method of the request
     pub method: &'a str,
     /// Headers of the request
-    pub headers: HashMap<&'a str, &'a str>,
+    pub headers: Headers,
+    /// Cookies sent with the request, parsed from the Cookie header
+    pub cookies: HashMap<String, String>,
     /// Query string of the request, empty string if none
     pub query_string: &'a str,
-    /// Body of the request
-    pub body: Option<&'a str>,
+    /// Decoded parameters of the query string, each name maps to every value in the order they
+    /// appear (ex: ?tag=a&tag=b -> {"tag": ["a", "b"]})
+    pub query: HashMap<String, Vec<String>>,
+    /// Body of the request, empty if there isn't one
+    #[serde(with = "serde_bytes", borrow)]
+    pub body: &'a [u8],
+    /// Only used with JSON, set when the body is base64 encoded because it isn't valid UTF-8
+    #[serde(default)]
+    pub is_base64_encoded: bool,
     /// The location of the script for the function that handles the request
     pub script: &'a str,
+    /// Pattern of the route that matched the request (ex: /users/{id})
+    pub route: &'a str,
+    /// Parameters captured by the route (ex: id for /users/{id}), the rest of the path matched by a
+    /// route ending in `*` is captured as `tail`
+    pub params: HashMap<String, String>,
+    /// Unique id of the request, taken from the x-request-id header when the client sets it
+    pub request_id: &'a str,
+    /// Address of the client, none if it isn't known
+    pub remote_addr: Option<&'a str>,
+    /// HTTP version of the request (ex: HTTP/1.1)
+    pub version: &'a str,
+    /// Scheme of the request, http or https
+    pub scheme: &'a str,
+    /// Host the request was made to, from the Host header or the address of the server
+    pub host: &'a str,
 }
 
 impl<'a> FunctionRequest<'a> {
     pub fn new(
+        function: &'a str,
         script: &'a str,
         path: &'a str,
         method: &'a str,
         query_string: &'a str,
     ) -> FunctionRequest<'a> {
         FunctionRequest {
+            function,
             script,
             path,
             method,
-            headers: HashMap::new(),
+            headers: Headers::new(),
+            cookies: HashMap::new(),
             query_string,
-            // @todo want to remove empty string and make it none but issue with nodejs and turbo-json-parse
-            body: Some(""),
+            query: HashMap::new(),
+            body: &[],
+            is_base64_encoded: false,
+            route: "",
+            params: HashMap::new(),
+            request_id: "",
+            remote_addr: None,
+            version: "",
+            scheme: "",
+            host: "",
         }
     }
 }
 
-impl<'a> ConvertFunction<'a> for FunctionRequest<'a> {}
+impl<'a> ConvertFunction<'a> for FunctionRequest<'a> {
+    fn to_string(&self) -> Result<String, Errors> {
+        let mut value =
+            serde_json::to_value(&self).map_err(|e| Errors::SerializationError(e.to_string()))?;
+
+        encode_json_body(&mut value)?;
+
+        serde_json::to_string(&value).map_err(|e| Errors::SerializationError(e.to_string()))
+    }
+}
 
 /// This is only used in the WebAssembly runtime. As of right now, only one value can be returned from a
 /// function making it difficult to get both a pointer and the size of the data to read. As a helper,
@@ -0,0 +1,66 @@
+use crate::body::encode_json_body;
+use crate::errors::Errors;
+use crate::{ConvertFunction, FunctionRequest};
+use serde::{Deserialize, Serialize};
+
+/// What happened on the connection
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebSocketEvent {
+    /// The client opened the connection, always the first event
+    Connect,
+    /// The client sent a message
+    Message,
+    /// The connection was closed by either side, always the last event
+    Disconnect,
+}
+
+/// Passed to a websocket function for each event of a connection. Similar to the FunctionContext,
+/// the request is the handshake that opened the connection.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebSocketContext<'a> {
+    pub event: WebSocketEvent,
+    /// Identifies the connection, the same for every event of the connection
+    pub connection_id: &'a str,
+    /// The handshake request, its body is always empty
+    #[serde(borrow = "'a")]
+    pub req: FunctionRequest<'a>,
+    /// The message sent by the client, empty unless the event is a message
+    #[serde(with = "serde_bytes", borrow)]
+    pub body: &'a [u8],
+    /// Only used with JSON, set when the body is base64 encoded because it isn't valid UTF-8
+    #[serde(default)]
+    pub is_base64_encoded: bool,
+    /// Set when the client sent the message as binary rather than text
+    #[serde(default)]
+    pub is_binary: bool,
+}
+
+impl<'a> WebSocketContext<'a> {
+    pub fn new(
+        event: WebSocketEvent,
+        connection_id: &'a str,
+        req: FunctionRequest<'a>,
+    ) -> WebSocketContext<'a> {
+        WebSocketContext {
+            event,
+            connection_id,
+            req,
+            body: &[],
+            is_base64_encoded: false,
+            is_binary: false,
+        }
+    }
+}
+
+impl<'a> ConvertFunction<'a> for WebSocketContext<'a> {
+    fn to_string(&self) -> Result<String, Errors> {
+        let mut value =
+            serde_json::to_value(&self).map_err(|e| Errors::SerializationError(e.to_string()))?;
+
+        encode_json_body(&mut value["req"])?;
+        encode_json_body(&mut value)?;
+
+        serde_json::to_string(&value).map_err(|e| Errors::SerializationError(e.to_string()))
+    }
+}
@@ -1,33 +1,248 @@
+use crate::secrets::{ConfigValue, SecretError, SecretProvider, SecretsConfig};
+use crate::tracing::TracingConfig;
 use failure::Fail;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 use std::process::Command;
 
+/// Runtimes that a function can be executed with
+pub const RUNTIMES: &[&str] = &["unix_socket", "wasm"];
+
+/// HTTP methods that the gateway is able to route to a function
+pub const SUPPORTED_METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS", "TRACE",
+];
+
+/// Name of the parameter that captures the rest of the path for a route ending in `*`
+pub const TAIL_PARAM: &str = "tail";
+
+/// Matches every method, except for HEAD and OPTIONS which are still answered by the gateway
+pub const ANY_METHOD: &str = "*";
+
 #[derive(Debug, Fail)]
 pub enum ConfigError {
     #[fail(display = "IO Error {}", _0)]
     IOError(std::io::Error),
 
-    #[fail(display = "Error parsing config file")]
+    #[fail(display = "Error parsing config file: {}", _0)]
     ParsingError(serde_yaml::Error),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct NetworkingConfig {
-    #[serde(default = Config::default_host())]
+    #[serde(default = "Config::default_host")]
     pub host: String,
-    #[serde(default = Config::default_port())]
+    #[serde(default = "Config::default_port")]
     pub port: String,
+    /// Number of worker threads handling requests
+    #[serde(default = "NetworkingConfig::default_workers")]
+    pub workers: usize,
+    /// Serve HTTPS rather than HTTP on the TCP listeners
+    pub tls: Option<TlsConfig>,
+    /// Addresses, sockets and socket activated sockets to listen on. The server listens on host and
+    /// port when there are none
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+}
+
+impl NetworkingConfig {
+    pub fn default_workers() -> usize {
+        1
+    }
+
+    /// Every listener of the server, a single TCP listener on host and port unless listeners are
+    /// configured
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+
+        vec![ListenerConfig {
+            bind: Bind::Tcp {
+                address: format!("{}:{}", self.host, self.port),
+            },
+            internal: false,
+        }]
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ListenerConfig {
+    #[serde(flatten)]
+    pub bind: Bind,
+    /// Only serve the admin API, metrics and health checks. When a listener is internal the admin
+    /// API and metrics are no longer served by the other listeners
+    #[serde(default)]
+    pub internal: bool,
+}
+
+/// What a listener is bound to, selected by `type`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Bind {
+    /// A TCP address, IPv6 addresses are in brackets (ex: 127.0.0.1:8080, [::1]:8080)
+    Tcp { address: String },
+    /// A unix domain socket, such as for a local reverse proxy. A socket left at the path by a
+    /// previous run is replaced, unless a server still accepts connections on it
+    Unix { path: String },
+    /// Sockets passed by systemd socket activation (LISTEN_FDS). Only the sockets with the name, set
+    /// by FileDescriptorName, are used when it's set, otherwise every socket is
+    Systemd {
+        #[serde(default)]
+        name: Option<String>,
+    },
+}
+
+impl Bind {
+    /// Host and port of a TCP address, none for other binds or when the port isn't valid
+    pub fn tcp_host_port(&self) -> Option<(&str, u16)> {
+        match self {
+            Bind::Tcp { address } => {
+                let index = address.rfind(':')?;
+                let port = address[index + 1..].parse().ok()?;
+
+                Some((&address[..index], port))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// TLS termination, the certificate and key are reloaded when either file changes so that they can
+/// be renewed without a restart
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain, starting with the server's certificate
+    pub cert: String,
+    /// PEM file with the private key of the certificate, PKCS#8 or RSA
+    pub key: String,
+    /// PEM file with the certificate authorities that client certificates must be signed by. When
+    /// set every client must present a certificate (mTLS)
+    pub client_ca: Option<String>,
+    /// Oldest version of TLS accepted
+    #[serde(default)]
+    pub min_version: TlsVersion,
+    /// Also listen for plain HTTP on this port, on the host of every TCP listener that isn't
+    /// internal, redirecting every request to HTTPS
+    pub redirect_port: Option<String>,
+    /// How often the certificate and key files are checked for changes
+    #[serde(default = "TlsConfig::default_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+}
+
+impl TlsConfig {
+    pub fn default_reload_interval_secs() -> u64 {
+        60
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum TlsVersion {
+    #[serde(rename = "TLSv1.2")]
+    Tls12,
+    #[serde(rename = "TLSv1.3")]
+    Tls13,
+}
+
+impl Default for TlsVersion {
+    fn default() -> TlsVersion {
+        TlsVersion::Tls12
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GatewayConfig {
+    /// Include the message and causes of errors in error responses. They can leak internal details
+    /// such as paths and addresses, so this is meant for development
+    #[serde(default)]
+    pub expose_errors: bool,
+    /// Largest request body accepted, in bytes, unless the function sets its own. Larger requests
+    /// are rejected with a 413
+    #[serde(default = "GatewayConfig::default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Whether the workers share the runtimes of the functions, `per_worker` by default
+    #[serde(default)]
+    pub runtime_store: RuntimeStore,
+}
+
+/// Where the runtimes of the functions are kept
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeStore {
+    /// Each worker starts its own runtime for every function, so each unix_socket function has a
+    /// process per worker and each wasm module is compiled by every worker
+    PerWorker,
+    /// A single runtime for every function, shared by all of the workers
+    Shared,
+}
+
+impl Default for RuntimeStore {
+    fn default() -> RuntimeStore {
+        RuntimeStore::PerWorker
+    }
+}
+
+impl GatewayConfig {
+    /// 10 MiB
+    pub fn default_max_body_bytes() -> usize {
+        10 * 1024 * 1024
+    }
+}
+
+/// The admin API, used to inspect and control the running functions. Only served when a token is
+/// set, and requires `gateway.runtime_store` to be shared when there is more than 1 worker
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AdminConfig {
+    /// Path the admin API is served under
+    #[serde(default = "AdminConfig::default_prefix")]
+    pub prefix: String,
+    /// Bearer token required by every request to the admin API. May reference a secret with
+    /// `secret://name`
+    pub token: Option<ConfigValue>,
+}
+
+impl AdminConfig {
+    pub fn default_prefix() -> String {
+        "/_admin".to_string()
+    }
+}
+
+impl Default for AdminConfig {
+    fn default() -> AdminConfig {
+        AdminConfig {
+            prefix: AdminConfig::default_prefix(),
+            token: None,
+        }
+    }
+}
+
+impl Default for GatewayConfig {
+    fn default() -> GatewayConfig {
+        GatewayConfig {
+            expose_errors: false,
+            max_body_bytes: GatewayConfig::default_max_body_bytes(),
+            runtime_store: RuntimeStore::default(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct FunctionConfig {
-    /// HTTP method that the function handles
-    pub method: String,
-    /// Route that the function is bound to
+    /// Unique name of the function, used as the cache key and whenever the function needs to be
+    /// identified. Derived from the method and route when not set (ex: GET /users -> get-users)
+    #[serde(default)]
+    pub name: String,
+    /// HTTP method or list of methods that the function handles, `*` for every method. HEAD is
+    /// handled by a GET function and OPTIONS is answered by the gateway, unless either is listed
+    pub method: Methods,
+    /// Route that the function is bound to. Segments can capture parameters, optionally matching a
+    /// regex (ex: /users/{id}/posts/{post_id:\d+}), and a trailing `*` captures the rest of the path
+    /// as the `tail` parameter (ex: /static/*)
     pub route: String,
     /// Path to the script which defines the function
     pub handler: String,
@@ -36,27 +251,312 @@ pub struct FunctionConfig {
     pub cmd: Option<String>,
     /// Runtime of the function (ex: wasm, unix_socket)
     pub runtime: String,
-    /// Generated automatically, used as the cache key
+    /// How the route is served, `http` by default or `websocket`
+    #[serde(rename = "type", default)]
+    pub route_type: RouteType,
+    /// Additional arguments passed to the handler, after the path of the socket. May reference a
+    /// secret with `secret://name`
+    #[serde(default)]
+    pub args: Vec<ConfigValue>,
+    /// Environment variables for the function. Set on the process for unix_socket functions and
+    /// readable through the `env_var` host function for wasm functions. Values may reference a
+    /// secret with `secret://name`
+    #[serde(default)]
+    pub env: HashMap<String, ConfigValue>,
+    /// Start the process with an empty environment instead of inheriting the pod's, `env` is still
+    /// applied. Only used by the unix_socket runtime
+    #[serde(default)]
+    pub env_clear: bool,
+    /// Working directory of the process, defaults to the working directory of the pod. Only used by
+    /// the unix_socket runtime
+    pub cwd: Option<String>,
+    /// Which of the request's headers are forwarded to the function, all of them by default
+    #[serde(default)]
+    pub forward_headers: HeaderFilter,
+    /// Restrictions applied to the process. Only used by the unix_socket runtime
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+    /// Place the process in its own cgroup v2 with these limits. Only used by the unix_socket
+    /// runtime, the function runs without the cgroup when the filesystem isn't writable
+    pub cgroup: Option<CgroupConfig>,
+    /// Largest request body accepted by the function, in bytes, overrides
+    /// `gateway.max_body_bytes`
+    pub max_body_bytes: Option<usize>,
+    /// Send the request body to the function as it arrives, over the framed protocol (see
+    /// `fn_api::frames`), instead of buffering it. Only supported by the unix_socket runtime
+    #[serde(default)]
+    pub stream_body: bool,
+    /// The function responds with a head frame followed by the body in chunks (see
+    /// `fn_api::frames`), which are forwarded to the client as they arrive. Used for server-sent
+    /// events when the content-type is `text/event-stream`. Only supported by the unix_socket runtime
+    #[serde(default)]
+    pub stream_response: bool,
+    /// Initialize the function when the pod starts instead of on its first request, the pod isn't
+    /// ready until it has
+    #[serde(default)]
+    pub warm: bool,
+    /// Request sent periodically to the function's process to check its health, the process is
+    /// restarted when the check fails. Only supported by the unix_socket runtime, for http routes
+    /// that don't stream their body or response
+    pub health_check: Option<HealthCheckConfig>,
+    /// Generated automatically, identifies this instance of the function. Unlike the name, the id
+    /// changes every time the config is loaded
     #[serde(default = "uuid::Uuid::new_v4")]
     pub id: Uuid,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteType {
+    /// Each request is sent to the function, which responds to it
+    Http,
+    /// The gateway accepts websocket connections on the route, the handshake must be a GET. Each
+    /// event of a connection is sent to the function as a WebSocketContext and the function can
+    /// push messages to the client
+    WebSocket,
+}
+
+impl Default for RouteType {
+    fn default() -> RouteType {
+        RouteType::Http
+    }
+}
+
+/// The HTTP methods of a function, either a single method or a list
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Methods {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Methods {
+    /// The methods as written in the config
+    pub fn declared(&self) -> Vec<&str> {
+        match self {
+            Methods::One(method) => vec![method.as_str()],
+            Methods::Many(methods) => methods.iter().map(|method| method.as_str()).collect(),
+        }
+    }
+
+    /// Every method handled, uppercased and with `*` expanded
+    pub fn list(&self) -> Vec<String> {
+        let mut methods: Vec<String> = vec![];
+
+        for method in self.declared() {
+            let expanded: Vec<String> = if method == ANY_METHOD {
+                SUPPORTED_METHODS
+                    .iter()
+                    .filter(|method| **method != "HEAD" && **method != "OPTIONS")
+                    .map(|method| method.to_string())
+                    .collect()
+            } else {
+                vec![method.to_uppercase()]
+            };
+
+            for method in expanded {
+                if !methods.contains(&method) {
+                    methods.push(method);
+                }
+            }
+        }
+
+        methods
+    }
+
+    pub fn contains(&self, method: &str) -> bool {
+        self.list().iter().any(|m| m == method)
+    }
+}
+
+impl From<&str> for Methods {
+    fn from(method: &str) -> Methods {
+        Methods::One(method.to_string())
+    }
+}
+
+/// Filters the headers of a request by name, case insensitive. A header is forwarded when it is in
+/// the allow list, or there is no allow list, and it isn't in the deny list.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct HeaderFilter {
+    pub allow: Option<Vec<String>>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl HeaderFilter {
+    pub fn allows(&self, name: &str) -> bool {
+        let allowed = match &self.allow {
+            Some(allow) => allow.iter().any(|header| header.eq_ignore_ascii_case(name)),
+            None => true,
+        };
+
+        allowed
+            && !self
+                .deny
+                .iter()
+                .any(|header| header.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Restrictions applied to the process of a unix_socket function, so that an untrusted or buggy
+/// handler can't take down the host. Everything is disabled by default.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SandboxConfig {
+    /// Resource limits applied with setrlimit
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Run the process as this user, requires the pod to run as root
+    pub uid: Option<u32>,
+    /// Run the process as this group, requires the pod to run as root
+    pub gid: Option<u32>,
+    /// Start the process in a new session and process group, when the function is shut down the
+    /// whole group is signaled, including any processes the handler started
+    #[serde(default)]
+    pub new_session: bool,
+    /// Linux namespaces to isolate the process in, requires CAP_SYS_ADMIN
+    #[serde(default)]
+    pub namespaces: NamespacesConfig,
+    /// Install a seccomp filter that denies syscalls a function shouldn't need, such as mount,
+    /// ptrace and reboot
+    #[serde(default)]
+    pub seccomp: bool,
+}
+
+impl SandboxConfig {
+    /// Whether the process leads its own process group, which is then signaled on shutdown
+    pub fn new_process_group(&self) -> bool {
+        // the handler isn't the process that was spawned when it runs in a PID namespace, so it has
+        // to be reached through the process group
+        self.new_session || self.namespaces.pid
+    }
+}
+
+/// Limits set with setrlimit, unset limits are inherited from the pod
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LimitsConfig {
+    /// Maximum CPU time in seconds (RLIMIT_CPU)
+    pub cpu_seconds: Option<u64>,
+    /// Maximum size of the virtual memory in bytes (RLIMIT_AS)
+    pub address_space: Option<u64>,
+    /// Maximum number of open file descriptors (RLIMIT_NOFILE)
+    pub open_files: Option<u64>,
+    /// Maximum number of processes for the user of the function (RLIMIT_NPROC)
+    pub processes: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct NamespacesConfig {
+    /// New mount namespace, mounts made by the function aren't visible to the host
+    #[serde(default)]
+    pub mount: bool,
+    /// New PID namespace, the handler runs as PID 1 and can't see or signal other processes
+    #[serde(default)]
+    pub pid: bool,
+    /// New network namespace with no interfaces besides loopback, the function can only be reached
+    /// through its unix socket
+    #[serde(default)]
+    pub network: bool,
+}
+
+/// Limits of the cgroup v2 created for each process of a function
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CgroupConfig {
+    /// Directory the function's cgroups are created in, the pod needs to be able to write to it
+    #[serde(default = "CgroupConfig::default_root")]
+    pub root: String,
+    /// Written to memory.max, the memory limit in bytes
+    pub memory_max: Option<u64>,
+    /// Written to cpu.max, the quota and period in microseconds (ex: "50000 100000" for half of a
+    /// CPU)
+    pub cpu_max: Option<String>,
+    /// Written to pids.max, the maximum number of processes
+    pub pids_max: Option<u64>,
+}
+
+impl CgroupConfig {
+    pub fn default_root() -> String {
+        "/sys/fs/cgroup/fn-rs".to_string()
+    }
+}
+
+/// The function is sent a request with the method and path, it's healthy when it responds with a
+/// status below 500
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HealthCheckConfig {
+    /// Path of the request, passed to the function as the path of the FunctionRequest
+    pub path: String,
+    #[serde(default = "HealthCheckConfig::default_method")]
+    pub method: String,
+    /// Seconds between each check
+    #[serde(default = "HealthCheckConfig::default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl HealthCheckConfig {
+    pub fn default_method() -> String {
+        "GET".to_string()
+    }
+
+    pub fn default_interval_secs() -> u64 {
+        10
+    }
+}
+
 impl FunctionConfig {
     #[allow(dead_code)]
     pub fn new(
-        method: String,
+        name: String,
+        method: Methods,
         route: String,
         handler: String,
         cmd: Option<String>,
         runtime: String,
     ) -> FunctionConfig {
-        FunctionConfig {
+        let mut config = FunctionConfig {
+            name,
             method,
             route,
             handler,
             cmd,
             runtime,
+            route_type: RouteType::Http,
+            args: vec![],
+            env: HashMap::new(),
+            env_clear: false,
+            cwd: None,
+            forward_headers: HeaderFilter::default(),
+            sandbox: SandboxConfig::default(),
+            cgroup: None,
+            max_body_bytes: None,
+            stream_body: false,
+            stream_response: false,
+            warm: false,
+            health_check: None,
             id: Uuid::new_v4(),
+        };
+
+        if config.name.is_empty() {
+            config.name = config.derived_name();
+        }
+
+        config
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The route as a pattern for the router, with a trailing `*` replaced by the `tail` parameter
+    pub fn route_pattern(&self) -> String {
+        if self.route.ends_with('*') {
+            format!(
+                "{}{{{}:.*}}",
+                &self.route[..self.route.len() - 1],
+                TAIL_PARAM
+            )
+        } else {
+            self.route.clone()
         }
     }
 
@@ -64,15 +564,61 @@ impl FunctionConfig {
         &self.id
     }
 
-    /// Create the command for executing the function
+    /// Name used when one isn't set in the config, the method and route joined by dashes with every
+    /// other character removed
+    pub fn derived_name(&self) -> String {
+        let raw = format!("{} {}", self.method.declared().join(" "), self.route).to_lowercase();
+
+        raw.split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<&str>>()
+            .join("-")
+    }
+
+    /// Create the command for executing the function, with the environment and working directory
+    /// applied. The path of the socket and `args` still need to be added
     pub fn cmd(&self) -> Command {
-        match &self.cmd {
+        let handler = self.handler_path();
+
+        let mut command = match &self.cmd {
             Some(cmd) => {
                 let mut command = Command::new(cmd.as_str());
-                command.arg(&self.handler);
+                command.arg(&handler);
                 command
             }
-            _ => Command::new(&self.handler),
+            _ => Command::new(&handler),
+        };
+
+        if self.env_clear {
+            command.env_clear();
+        }
+
+        command.envs(self.env.iter().map(|(key, value)| (key, value.value())));
+
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+
+        command
+    }
+
+    /// Replace every `secret://` reference in the config with the value from the provider
+    pub fn resolve_secrets(&mut self, provider: &dyn SecretProvider) -> Result<(), SecretError> {
+        for value in self.env.values_mut().chain(self.args.iter_mut()) {
+            value.resolve(provider)?;
+        }
+
+        Ok(())
+    }
+
+    /// Path of the handler. When the function has its own working directory the path is made
+    /// absolute, since a relative path would otherwise be ambiguous once the directory changes
+    fn handler_path(&self) -> PathBuf {
+        let handler = PathBuf::from(&self.handler);
+
+        match &self.cwd {
+            Some(_) if handler.is_relative() => std::fs::canonicalize(&handler).unwrap_or(handler),
+            _ => handler,
         }
     }
 }
@@ -81,18 +627,74 @@ impl FunctionConfig {
 pub struct Config {
     networking: NetworkingConfig,
     functions: Vec<FunctionConfig>,
+    #[serde(default)]
+    secrets: SecretsConfig,
+    #[serde(default)]
+    gateway: GatewayConfig,
+    #[serde(default)]
+    tracing: TracingConfig,
+    #[serde(default)]
+    admin: AdminConfig,
+    /// File the config was loaded from
+    #[serde(skip)]
+    path: PathBuf,
 }
 
 impl Config {
+    /// Where the config is loaded from by default
+    pub const DEFAULT_PATH: &'static str = "config.yaml";
+
     /// Attempt to load the config
     pub fn load() -> Result<Config, ConfigError> {
-        // @todo probably should be configurable in some way
-        let filename = "config.yaml";
+        Config::load_from(Config::DEFAULT_PATH)
+    }
 
-        let file = File::open(&filename).map_err(|e| ConfigError::IOError(e))?;
+    /// Load the config from the file at the path
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let file = File::open(path.as_ref()).map_err(|e| ConfigError::IOError(e))?;
         let reader = BufReader::new(file);
 
-        serde_yaml::from_reader(reader).map_err(|e| ConfigError::ParsingError(e))
+        let mut config: Config =
+            serde_yaml::from_reader(reader).map_err(|e| ConfigError::ParsingError(e))?;
+
+        config.path = path.as_ref().to_path_buf();
+
+        for func in config.functions.iter_mut() {
+            if func.name.is_empty() {
+                func.name = func.derived_name();
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn networking(&self) -> &NetworkingConfig {
+        &self.networking
+    }
+
+    /// Used to override the networking config, such as from the command line
+    pub fn networking_mut(&mut self) -> &mut NetworkingConfig {
+        &mut self.networking
+    }
+
+    pub fn gateway(&self) -> &GatewayConfig {
+        &self.gateway
+    }
+
+    pub fn secrets(&self) -> &SecretsConfig {
+        &self.secrets
+    }
+
+    pub fn tracing(&self) -> &TracingConfig {
+        &self.tracing
+    }
+
+    pub fn admin(&self) -> &AdminConfig {
+        &self.admin
     }
 
     pub fn functions(&self) -> &Vec<FunctionConfig> {
@@ -103,7 +705,7 @@ impl Config {
         (&self.functions).iter()
     }
 
-    /// Formatted address to bind the HTTP server to
+    /// Formatted address of networking.host and networking.port
     pub fn address(&self) -> String {
         format!("{}:{}", &self.networking.host, &self.networking.port)
     }
@@ -115,6 +717,51 @@ impl Config {
 
     #[allow(dead_code)]
     pub fn default_port() -> String {
-        return "80".to_string();
+        return "8080".to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_host_and_port() {
+        let config: Config =
+            serde_yaml::from_str("networking:\n  workers: 2\nfunctions: []\n").unwrap();
+
+        assert_eq!(config.address(), "0.0.0.0:8080");
+
+        let config: Config =
+            serde_yaml::from_str("networking:\n  host: 127.0.0.1\nfunctions: []\n").unwrap();
+
+        assert_eq!(config.address(), "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn tcp_host_port() {
+        let tcp = |address: &str| Bind::Tcp {
+            address: address.to_string(),
+        };
+
+        assert_eq!(
+            tcp("127.0.0.1:8080").tcp_host_port(),
+            Some(("127.0.0.1", 8080))
+        );
+        assert_eq!(tcp("[::1]:8443").tcp_host_port(), Some(("[::1]", 8443)));
+        assert_eq!(tcp("localhost:80").tcp_host_port(), Some(("localhost", 80)));
+
+        // missing and invalid ports
+        assert_eq!(tcp("127.0.0.1").tcp_host_port(), None);
+        assert_eq!(tcp("[::1]").tcp_host_port(), None);
+        assert_eq!(tcp("127.0.0.1:http").tcp_host_port(), None);
+        assert_eq!(tcp("127.0.0.1:65536").tcp_host_port(), None);
+
+        let unix = Bind::Unix {
+            path: "/tmp/fn.sock".to_string(),
+        };
+
+        assert_eq!(unix.tcp_host_port(), None);
+        assert_eq!(Bind::Systemd { name: None }.tcp_host_port(), None);
     }
 }
@@ -1,6 +1,16 @@
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate prometheus;
+
 pub mod config;
 pub mod errors;
+pub mod metrics;
+pub mod query;
 pub mod runtime;
+pub mod secrets;
 pub mod state;
+pub mod tracing;
+pub mod validation;
 
 use failure;
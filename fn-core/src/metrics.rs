@@ -0,0 +1,149 @@
+//! Metrics of the pod and its functions, exported in the Prometheus text format. Every metric of a
+//! function is labeled with the function's name and route.
+
+use crate::config::FunctionConfig;
+use crate::runtime::duration_seconds;
+use crate::state::AppData;
+use prometheus::{Encoder, GaugeVec, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder};
+use std::time::Duration;
+
+/// Buckets for cold starts, which include waiting for the process to create its socket
+const COLD_START_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 1.5, 2.5, 5.0, 10.0];
+
+lazy_static! {
+    pub static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "fn_requests_total",
+        "Requests handled by each function, by the status of the response",
+        &["function", "route", "status"]
+    )
+    .unwrap();
+    pub static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "fn_request_duration_seconds",
+        "Time taken to respond to a request, for a streamed response until its head is sent",
+        &["function", "route"]
+    )
+    .unwrap();
+    pub static ref QUEUE_DEPTH: IntGaugeVec = register_int_gauge_vec!(
+        "fn_queue_depth",
+        "Requests that are waiting for or being handled by each function",
+        &["function", "route"]
+    )
+    .unwrap();
+    pub static ref COLD_STARTS: IntCounterVec = register_int_counter_vec!(
+        "fn_cold_starts_total",
+        "Runtimes initialized for each function",
+        &["function", "route", "runtime"]
+    )
+    .unwrap();
+    pub static ref COLD_START_DURATION: HistogramVec = register_histogram_vec!(
+        histogram_opts!(
+            "fn_cold_start_duration_seconds",
+            "Time taken to initialize the runtime of a function",
+            COLD_START_BUCKETS.to_vec()
+        ),
+        &["function", "route", "runtime"]
+    )
+    .unwrap();
+    pub static ref ACTIVE_RUNTIMES: IntGaugeVec = register_int_gauge_vec!(
+        "fn_active_runtimes",
+        "Runtimes of each function that are initialized, there is one per worker",
+        &["function", "route", "runtime"]
+    )
+    .unwrap();
+    pub static ref PROCESS_RESTARTS: IntCounterVec = register_int_counter_vec!(
+        "fn_process_restarts_total",
+        "Processes of unix_socket functions restarted after exiting",
+        &["function", "route"]
+    )
+    .unwrap();
+    pub static ref WASM_TRAPS: IntCounterVec = register_int_counter_vec!(
+        "fn_wasm_traps_total",
+        "Traps raised while wasm functions handled a request",
+        &["function", "route"]
+    )
+    .unwrap();
+    pub static ref TIMEOUTS: IntCounterVec = register_int_counter_vec!(
+        "fn_timeouts_total",
+        "Requests where the function didn't respond in time",
+        &["function", "route", "runtime"]
+    )
+    .unwrap();
+    pub static ref MEMORY_BYTES: IntGaugeVec = register_int_gauge_vec!(
+        "fn_memory_bytes",
+        "Memory used by the processes of each function, from its cgroup",
+        &["function", "route"]
+    )
+    .unwrap();
+    pub static ref CPU_SECONDS: GaugeVec = register_gauge_vec!(
+        "fn_cpu_seconds",
+        "CPU time used by the processes of each function since they started, from its cgroup",
+        &["function", "route"]
+    )
+    .unwrap();
+    pub static ref PIDS: IntGaugeVec = register_int_gauge_vec!(
+        "fn_pids",
+        "Processes running for each function, from its cgroup",
+        &["function", "route"]
+    )
+    .unwrap();
+}
+
+/// Set the resource usage gauges from the runtimes of the states, called when the metrics are
+/// scraped. The usage of a function is summed over the workers that run it. Functions that aren't
+/// running, or don't track their usage, have no value
+pub fn record_resource_usage(states: &[AppData], functions: &[FunctionConfig]) {
+    MEMORY_BYTES.reset();
+    CPU_SECONDS.reset();
+    PIDS.reset();
+
+    for (state, func) in states
+        .iter()
+        .flat_map(|state| functions.iter().map(move |func| (state, func)))
+    {
+        let runtime = match state.handles.read().get(func.name()) {
+            Some(runtime) => runtime.clone(),
+            None => continue,
+        };
+
+        // a runtime that's locked for writing is skipped rather than holding up the scrape
+        let lock_guard = match runtime.try_read() {
+            Some(lock_guard) => lock_guard,
+            None => continue,
+        };
+        let usage = lock_guard.resource_usage();
+        drop(lock_guard);
+
+        let usage = match usage {
+            Some(usage) => usage,
+            None => continue,
+        };
+
+        let labels = [func.name(), func.route.as_str()];
+
+        if let Some(memory_bytes) = usage.memory_bytes {
+            MEMORY_BYTES
+                .with_label_values(&labels)
+                .add(memory_bytes as i64);
+        }
+
+        if let Some(cpu_usec) = usage.cpu_usec {
+            CPU_SECONDS
+                .with_label_values(&labels)
+                .add(duration_seconds(Duration::from_micros(cpu_usec)));
+        }
+
+        if let Some(pids) = usage.pids {
+            PIDS.with_label_values(&labels).add(pids as i64);
+        }
+    }
+}
+
+/// Encode every metric in the Prometheus text format, returns the content type and the body
+pub fn encode() -> Result<(String, Vec<u8>), prometheus::Error> {
+    let encoder = TextEncoder::new();
+    let mut buf = vec![];
+
+    encoder.encode(&prometheus::gather(), &mut buf)?;
+
+    Ok((encoder.format_type().to_string(), buf))
+}
@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+/// Decode the query string into a map of each name to all of its values, a malformed query string
+/// results in an empty map since the raw string is still passed along
+pub fn parse(query_string: &str) -> HashMap<String, Vec<String>> {
+    let pairs: Vec<(String, String)> =
+        serde_urlencoded::from_str(query_string).unwrap_or_else(|_| vec![]);
+
+    let mut query: HashMap<String, Vec<String>> = HashMap::new();
+
+    for (name, value) in pairs {
+        query.entry(name).or_insert_with(Vec::new).push(value);
+    }
+
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_every_value_of_a_name() {
+        let query = parse("name=kyle&tag=a&tag=b%20c&empty=");
+
+        assert_eq!(query["name"], vec!["kyle"]);
+        assert_eq!(query["tag"], vec!["a", "b c"]);
+        assert_eq!(query["empty"], vec![""]);
+        assert_eq!(query.len(), 3);
+    }
+
+    #[test]
+    fn empty_query_string() {
+        assert!(parse("").is_empty());
+    }
+}
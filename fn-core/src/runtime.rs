@@ -1,9 +1,13 @@
 use crate::config::FunctionConfig;
-use crate::state::AppData;
+use crate::metrics;
+use crate::state::{AppData, FunctionStatus, State};
+use crate::tracing::{Span, SpanContext};
 use failure::{Error, Fail};
-use fn_api::FunctionContext;
+use fn_api::websocket::WebSocketContext;
+use fn_api::{ConvertFunction, FunctionContext, FunctionResponse};
 use parking_lot::RwLock;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Fail)]
 pub enum RuntimeError {
@@ -24,43 +28,170 @@ pub enum RuntimeError {
 
     #[fail(display = "Runtime deleted from hash map while fetching")]
     RaceError,
+
+    #[fail(display = "Failed to initialize function {}: {}", _0, _1)]
+    StartupError(String, Error),
+
+    #[fail(display = "Failed to communicate with function {}: {}", _0, _1)]
+    CommunicationError(String, String),
+
+    #[fail(display = "Function {} did not respond within {}ms", _0, _1)]
+    Timeout(String, u64),
+
+    #[fail(display = "Function {} crashed while handling the request: {}", _0, _1)]
+    Crashed(String, String),
+
+    #[fail(display = "Function {} returned a malformed response: {}", _0, _1)]
+    MalformedResponse(String, String),
+
+    #[fail(display = "The runtime does not support streaming")]
+    StreamingUnsupported,
+
+    #[fail(display = "The runtime does not support websockets")]
+    WebSocketUnsupported,
+
+    #[fail(display = "Function {} is draining and not accepting requests", _0)]
+    Draining(String),
+}
+
+/// Broad categories of errors, used by the gateway to choose the status of the response
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorKind {
+    /// The runtime of the function couldn't be initialized
+    Unavailable,
+    /// The runtime is running but couldn't be communicated with
+    BadGateway,
+    /// The function didn't respond in time
+    Timeout,
+    /// The function failed while handling the request, such as a trap in wasm
+    Crashed,
+    /// The function responded with something that isn't a FunctionResponse
+    MalformedResponse,
+    /// Anything else
+    Internal,
+}
+
+impl ErrorKind {
+    /// Classify an error returned by a runtime
+    pub fn of(error: &Error) -> ErrorKind {
+        match error.downcast_ref::<RuntimeError>() {
+            Some(RuntimeError::InitializationError)
+            | Some(RuntimeError::StartupError(_, _))
+            | Some(RuntimeError::Draining(_)) => ErrorKind::Unavailable,
+            Some(RuntimeError::CommunicationError(_, _)) => ErrorKind::BadGateway,
+            Some(RuntimeError::Timeout(_, _)) => ErrorKind::Timeout,
+            Some(RuntimeError::Crashed(_, _)) => ErrorKind::Crashed,
+            Some(RuntimeError::MalformedResponse(_, _)) => ErrorKind::MalformedResponse,
+            _ => ErrorKind::Internal,
+        }
+    }
+}
+
+/// Resource usage of a runtime, for the runtimes that are able to measure it
+#[derive(Clone, Debug, Default)]
+pub struct ResourceUsage {
+    /// Memory currently used, in bytes
+    pub memory_bytes: Option<u64>,
+    /// Total CPU time used, in microseconds
+    pub cpu_usec: Option<u64>,
+    /// Number of processes currently running
+    pub pids: Option<u64>,
+}
+
+/// A request whose body is sent to the function as it arrives, see `FunctionConfig::stream_body`
+pub trait StreamingRequest {
+    /// Send the next chunk of the body to the function
+    fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), failure::Error>;
+
+    /// Signal the end of the body and wait for the function's response
+    fn finish(self: Box<Self>) -> Result<Vec<u8>, failure::Error>;
+
+    /// Signal the end of the body and wait for the head of the function's streamed response
+    fn finish_streaming(self: Box<Self>) -> Result<StreamingResponse, failure::Error> {
+        Err(RuntimeError::StreamingUnsupported)?
+    }
+}
+
+/// The response of a function that streams its body, see `FunctionConfig::stream_response`
+pub struct StreamingResponse {
+    /// The status, headers and cookies of the response, as a CBOR FunctionResponse with an empty
+    /// body
+    pub head: Vec<u8>,
+    /// Each chunk of the body as the function sends it, reading the next chunk blocks until it
+    /// arrives
+    pub body: Box<dyn Iterator<Item = Result<Vec<u8>, failure::Error>> + Send>,
 }
 
-/// A runtime can be defined to allow for different approaches to function invocation
-pub trait RuntimeManager {
+impl StreamingResponse {
+    /// Wait for the whole body and put it in the response, for callers that don't stream it on
+    pub fn collect(self) -> Result<FunctionResponse, failure::Error> {
+        let mut res = FunctionResponse::from_slice(&self.head)?;
+
+        for chunk in self.body {
+            res.body.extend(chunk?);
+        }
+
+        Ok(res)
+    }
+}
+
+/// A message pushed by a websocket function to its client
+#[derive(Clone, Debug)]
+pub enum WebSocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    /// Close the connection
+    Close,
+}
+
+/// Called with each message the function pushes to the client, from any thread
+pub type MessageHandler = Box<dyn Fn(WebSocketMessage) + Send + Sync>;
+
+/// A connection to a function for the lifetime of a websocket, see `RouteType::WebSocket`
+pub trait WebSocketConnection {
+    /// Send an event of the websocket to the function
+    fn send(&mut self, event: WebSocketContext) -> Result<(), failure::Error>;
+}
+
+/// A runtime can be defined to allow for different approaches to function invocation. Runtimes may
+/// be shared by every worker, see `RuntimeStore::Shared`, so they must be thread safe
+pub trait RuntimeManager: Send + Sync {
     fn find_or_initialize(
         data: AppData,
         config: &FunctionConfig,
+        parent: SpanContext,
     ) -> Result<Arc<RwLock<dyn RuntimeManager>>, failure::Error>
     where
         Self: Sized + 'static,
     {
-        let handles_read = data.handles.read();
+        let runtime = match data.runtime(config.name()) {
+            Some(runtime) => runtime,
+            None => {
+                let start_lock = data.start_lock(config.name());
+                let _starting = start_lock.lock();
 
-        let contains_key = handles_read.contains_key(config.id());
+                // another request may have initialized the function while waiting for the lock
+                match data.runtime(config.name()) {
+                    Some(runtime) => runtime,
+                    None => cold_start::<Self>(&data, config, parent)?,
+                }
+            }
+        };
 
-        drop(handles_read);
+        // a runtime whose process has exited, such as after a crash, is restarted in place
+        if runtime.read().has_exited() {
+            let mut runtime_write = runtime.write();
 
-        if !contains_key {
-            let mut handles_write = data.handles.write();
-            let runtime = Self::initialize(&config)?;
-
-            handles_write.insert(config.id().clone(), runtime);
-
-            drop(handles_write);
+            if runtime_write.has_exited() {
+                restart(&data, config, &mut *runtime_write)?;
+            }
         }
 
-        let handles_read = data.handles.read();
-
-        let runtime = handles_read
-            .get(config.id())
-            .ok_or(RuntimeError::RaceError)?;
-
-        Ok(runtime.clone())
+        Ok(runtime)
     }
 
     /// Used to initialize a function that is cold. If successful, the runtime will be inserted into
-    /// a cache, keyed by the ID
+    /// a cache, keyed by the name of the function
     fn initialize(config: &FunctionConfig) -> Result<Arc<RwLock<Self>>, failure::Error>
     where
         Self: Sized;
@@ -69,4 +200,181 @@ pub trait RuntimeManager {
     fn shutdown(&mut self) -> Result<(), failure::Error>;
 
     fn handle_request(&self, ctx: FunctionContext) -> Result<Vec<u8>, failure::Error>;
+
+    /// Send the request to the function and wait for the head of its streamed response
+    fn handle_streaming(&self, _ctx: FunctionContext) -> Result<StreamingResponse, failure::Error> {
+        Err(RuntimeError::StreamingUnsupported)?
+    }
+
+    /// Open a connection to the function for a websocket, messages pushed by the function are
+    /// passed to the handler
+    fn connect_websocket(
+        &self,
+        _on_message: MessageHandler,
+    ) -> Result<Box<dyn WebSocketConnection>, failure::Error> {
+        Err(RuntimeError::WebSocketUnsupported)?
+    }
+
+    /// Start a request whose body is written afterwards, the body of the context is ignored
+    fn stream_request(
+        &self,
+        _ctx: FunctionContext,
+    ) -> Result<Box<dyn StreamingRequest>, failure::Error> {
+        Err(RuntimeError::StreamingUnsupported)?
+    }
+
+    /// Whether the function's process has exited, only runtimes with a process can exit. Once it
+    /// has, it stays exited until the runtime is restarted
+    fn has_exited(&self) -> bool {
+        false
+    }
+
+    /// Start the function again after it has exited
+    fn restart(&mut self) -> Result<(), failure::Error> {
+        Ok(())
+    }
+
+    /// Current resource usage of the function, none if the runtime doesn't track it
+    fn resource_usage(&self) -> Option<ResourceUsage> {
+        None
+    }
+
+    /// Id of the function's process, none if the runtime doesn't have one
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Initialize the runtime of a cold function and add it to the cache
+fn cold_start<R>(
+    data: &State,
+    config: &FunctionConfig,
+    parent: SpanContext,
+) -> Result<Arc<RwLock<dyn RuntimeManager>>, failure::Error>
+where
+    R: RuntimeManager + 'static,
+{
+    // secrets are only resolved for the copy of the config owned by the runtime
+    let mut config = config.clone();
+    config
+        .resolve_secrets(data.secrets.as_ref())
+        .map_err(|e| RuntimeError::StartupError(config.name().to_string(), e.into()))?;
+
+    let labels = [
+        config.name(),
+        config.route.as_str(),
+        config.runtime.as_str(),
+    ];
+    let started = Instant::now();
+
+    let mut span = Span::start("cold start", Some(parent));
+    span.set_attribute("function", config.name());
+    span.set_attribute("runtime", &config.runtime);
+
+    data.set_status(config.name(), FunctionStatus::Starting);
+
+    let runtime: Arc<RwLock<dyn RuntimeManager>> = R::initialize(&config).map_err(|e| {
+        span.set_error(&e);
+        data.set_status(config.name(), FunctionStatus::Crashed);
+        RuntimeError::StartupError(config.name().to_string(), e)
+    })?;
+
+    drop(span);
+
+    data.set_status(config.name(), FunctionStatus::Ready);
+
+    metrics::COLD_STARTS.with_label_values(&labels).inc();
+    metrics::COLD_START_DURATION
+        .with_label_values(&labels)
+        .observe(duration_seconds(started.elapsed()));
+    metrics::ACTIVE_RUNTIMES.with_label_values(&labels).inc();
+
+    data.handles
+        .write()
+        .insert(config.name().to_string(), runtime.clone());
+
+    Ok(runtime)
+}
+
+/// Restart a runtime that crashed, tracking its status while it restarts
+pub fn restart(
+    state: &State,
+    config: &FunctionConfig,
+    runtime: &mut dyn RuntimeManager,
+) -> Result<(), failure::Error> {
+    state.set_status(config.name(), FunctionStatus::Restarting);
+
+    if let Err(e) = runtime.restart() {
+        state.set_status(config.name(), FunctionStatus::Crashed);
+        Err(RuntimeError::StartupError(config.name().to_string(), e))?;
+    }
+
+    metrics::PROCESS_RESTARTS
+        .with_label_values(&[config.name(), &config.route])
+        .inc();
+
+    state.set_status(config.name(), FunctionStatus::Ready);
+
+    Ok(())
+}
+
+/// Shut down the runtime of the function and remove it from the cache, the next request starts it
+/// cold. False if the function wasn't running
+pub fn evict(state: &State, config: &FunctionConfig) -> Result<bool, failure::Error> {
+    let runtime = match state.handles.write().remove(config.name()) {
+        Some(runtime) => runtime,
+        None => return Ok(false),
+    };
+
+    metrics::ACTIVE_RUNTIMES
+        .with_label_values(&[config.name(), &config.route, &config.runtime])
+        .dec();
+
+    state.set_status(config.name(), FunctionStatus::Cold);
+
+    let mut lock_guard = runtime.write();
+    lock_guard
+        .shutdown()
+        .map_err(|e| RuntimeError::ShutdownError(e))?;
+
+    Ok(true)
+}
+
+/// Duration as fractional seconds, the unit of every duration in the metrics
+pub fn duration_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn streamed(chunks: Vec<Result<Vec<u8>, failure::Error>>) -> StreamingResponse {
+        let mut head = FunctionResponse::new();
+        head.status_code = 201;
+        head.headers.insert("content-type", "text/plain");
+
+        StreamingResponse {
+            head: head.to_bytes().unwrap(),
+            body: Box::new(chunks.into_iter()),
+        }
+    }
+
+    #[test]
+    fn collect_appends_the_chunks_to_the_head() {
+        let res = streamed(vec![Ok(b"hello ".to_vec()), Ok(b"world".to_vec())])
+            .collect()
+            .unwrap();
+
+        assert_eq!(res.status_code, 201);
+        assert_eq!(res.headers.get("content-type"), Some("text/plain"));
+        assert_eq!(res.body, b"hello world".to_vec());
+    }
+
+    #[test]
+    fn collect_fails_when_a_chunk_fails() {
+        let chunks = vec![Ok(b"hello".to_vec()), Err(RuntimeError::LockError.into())];
+
+        assert!(streamed(chunks).collect().is_err());
+    }
 }
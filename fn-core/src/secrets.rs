@@ -0,0 +1,503 @@
+use failure::Fail;
+use ring::aead::{self, Aad, Nonce, OpeningKey, SealingKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// Prefix of a config value that references a secret (ex: secret://database_url)
+pub const SECRET_PREFIX: &str = "secret://";
+
+#[derive(Debug, Fail)]
+pub enum SecretError {
+    #[fail(display = "Secret {} could not be found", _0)]
+    NotFound(String),
+
+    #[fail(display = "Secret name \"{}\" is invalid", _0)]
+    InvalidName(String),
+
+    #[fail(display = "Failed to read secret {}: {}", _0, _1)]
+    IOError(String, std::io::Error),
+
+    #[fail(display = "Failed to read the secrets key file: {}", _0)]
+    KeyFileError(std::io::Error),
+
+    #[fail(display = "Secrets key must be 32 bytes, encoded as 64 hex characters")]
+    InvalidKey,
+
+    #[fail(display = "Failed to read the encrypted secrets file: {}", _0)]
+    EncryptedFileError(std::io::Error),
+
+    #[fail(display = "Failed to decrypt the secrets file, the key or file is invalid")]
+    DecryptionError,
+
+    #[fail(display = "Failed to encrypt the secrets")]
+    EncryptionError,
+
+    #[fail(display = "Failed to parse the decrypted secrets: {}", _0)]
+    ParsingError(serde_yaml::Error),
+}
+
+/// A source of secrets, used to resolve the `secret://` references in a function's config when the
+/// function's runtime is initialized
+pub trait SecretProvider: Send + Sync {
+    fn get(&self, name: &str) -> Result<String, SecretError>;
+}
+
+/// Which provider to resolve secrets with
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum SecretsConfig {
+    /// Each secret is a file in the directory, named after the secret
+    File { path: String },
+    /// Each secret is an environment variable of the pod, named after the secret with the prefix
+    Env {
+        #[serde(default)]
+        prefix: String,
+    },
+    /// All of the secrets are stored in a single YAML map, encrypted with AES-256-GCM. The key file
+    /// contains the 32 byte key encoded as hex
+    EncryptedFile { path: String, key_file: String },
+}
+
+impl Default for SecretsConfig {
+    fn default() -> SecretsConfig {
+        SecretsConfig::Env {
+            prefix: String::new(),
+        }
+    }
+}
+
+impl SecretsConfig {
+    /// Create the provider, the encrypted file is read and decrypted once here
+    pub fn provider(&self) -> Result<Box<dyn SecretProvider>, SecretError> {
+        Ok(match self {
+            SecretsConfig::File { path } => Box::new(FileProvider::new(path)),
+            SecretsConfig::Env { prefix } => Box::new(EnvProvider::new(prefix)),
+            SecretsConfig::EncryptedFile { path, key_file } => {
+                Box::new(EncryptedFileProvider::load(path, key_file)?)
+            }
+        })
+    }
+}
+
+/// Reads each secret from a file in a directory, trailing newlines are removed
+pub struct FileProvider {
+    dir: PathBuf,
+}
+
+impl FileProvider {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FileProvider {
+        FileProvider { dir: dir.into() }
+    }
+}
+
+impl SecretProvider for FileProvider {
+    fn get(&self, name: &str) -> Result<String, SecretError> {
+        // don't allow a secret to escape the directory
+        if name.is_empty() || name.contains('/') || name.starts_with('.') {
+            return Err(SecretError::InvalidName(name.to_string()));
+        }
+
+        match fs::read_to_string(self.dir.join(name)) {
+            Ok(value) => Ok(value
+                .trim_end_matches(|c| c == '\n' || c == '\r')
+                .to_string()),
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(SecretError::NotFound(name.to_string()))
+            }
+            Err(e) => Err(SecretError::IOError(name.to_string(), e)),
+        }
+    }
+}
+
+/// Reads each secret from the pod's environment
+pub struct EnvProvider {
+    prefix: String,
+}
+
+impl EnvProvider {
+    pub fn new(prefix: &str) -> EnvProvider {
+        EnvProvider {
+            prefix: prefix.to_string(),
+        }
+    }
+}
+
+impl SecretProvider for EnvProvider {
+    fn get(&self, name: &str) -> Result<String, SecretError> {
+        std::env::var(format!("{}{}", self.prefix, name))
+            .map_err(|_| SecretError::NotFound(name.to_string()))
+    }
+}
+
+/// Secrets stored in a file encrypted at rest. The file is the 12 byte nonce followed by the
+/// AES-256-GCM ciphertext and tag of a YAML map of secret names to values
+pub struct EncryptedFileProvider {
+    secrets: HashMap<String, String>,
+}
+
+impl EncryptedFileProvider {
+    pub fn load(path: &str, key_file: &str) -> Result<EncryptedFileProvider, SecretError> {
+        let key = read_key(key_file)?;
+        let data = fs::read(path).map_err(|e| SecretError::EncryptedFileError(e))?;
+
+        if data.len() < NONCE_LEN {
+            return Err(SecretError::DecryptionError);
+        }
+
+        let opening_key =
+            OpeningKey::new(&AES_256_GCM, &key).map_err(|_| SecretError::InvalidKey)?;
+        let nonce = Nonce::try_assume_unique_for_key(&data[..NONCE_LEN])
+            .map_err(|_| SecretError::DecryptionError)?;
+
+        let mut in_out = data[NONCE_LEN..].to_vec();
+
+        let plaintext = aead::open_in_place(&opening_key, nonce, Aad::empty(), 0, &mut in_out)
+            .map_err(|_| SecretError::DecryptionError)?;
+
+        let secrets =
+            serde_yaml::from_slice(plaintext).map_err(|e| SecretError::ParsingError(e))?;
+
+        Ok(EncryptedFileProvider { secrets })
+    }
+
+    /// Encrypt a YAML map of secrets with the key, the output is the format read by `load`
+    pub fn encrypt(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, SecretError> {
+        // checked now rather than when the file is loaded by the server
+        serde_yaml::from_slice::<HashMap<String, String>>(plaintext)
+            .map_err(|e| SecretError::ParsingError(e))?;
+
+        let sealing_key =
+            SealingKey::new(&AES_256_GCM, key).map_err(|_| SecretError::InvalidKey)?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| SecretError::EncryptionError)?;
+
+        let tag_len = AES_256_GCM.tag_len();
+
+        let mut in_out = plaintext.to_vec();
+        in_out.extend(vec![0u8; tag_len]);
+
+        let len = aead::seal_in_place(
+            &sealing_key,
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+            tag_len,
+        )
+        .map_err(|_| SecretError::EncryptionError)?;
+
+        let mut output = nonce.to_vec();
+        output.extend_from_slice(&in_out[..len]);
+
+        Ok(output)
+    }
+}
+
+impl SecretProvider for EncryptedFileProvider {
+    fn get(&self, name: &str) -> Result<String, SecretError> {
+        self.secrets
+            .get(name)
+            .cloned()
+            .ok_or_else(|| SecretError::NotFound(name.to_string()))
+    }
+}
+
+/// Read the 32 byte key, encoded as hex, from the file
+pub fn read_key(key_file: &str) -> Result<Vec<u8>, SecretError> {
+    let encoded = fs::read_to_string(key_file).map_err(|e| SecretError::KeyFileError(e))?;
+
+    parse_key(&encoded)
+}
+
+/// A random key for the encrypted file provider, encoded as hex
+pub fn generate_key() -> Result<String, SecretError> {
+    let mut key = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| SecretError::EncryptionError)?;
+
+    Ok(key.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn parse_key(encoded: &str) -> Result<Vec<u8>, SecretError> {
+    let encoded = encoded.trim();
+
+    // from_str_radix would also accept a sign in front of a byte
+    if encoded.len() != 64 || !encoded.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(SecretError::InvalidKey);
+    }
+
+    (0..encoded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&encoded[i..i + 2], 16).map_err(|_| SecretError::InvalidKey))
+        .collect()
+}
+
+/// A string from the config which may reference a secret with `secret://name`. The reference is
+/// replaced by the value of the secret when resolved, the value is never included in the `Debug`
+/// output or when serialized.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub struct ConfigValue {
+    raw: String,
+    resolved: Option<String>,
+}
+
+impl ConfigValue {
+    /// Name of the referenced secret, none if the value is a plain string
+    pub fn secret_name(&self) -> Option<&str> {
+        if self.raw.starts_with(SECRET_PREFIX) {
+            Some(&self.raw[SECRET_PREFIX.len()..])
+        } else {
+            None
+        }
+    }
+
+    pub fn is_secret(&self) -> bool {
+        self.secret_name().is_some()
+    }
+
+    /// The value to use, for a secret this is the reference until it has been resolved
+    pub fn value(&self) -> &str {
+        match &self.resolved {
+            Some(resolved) => resolved,
+            None => &self.raw,
+        }
+    }
+
+    pub fn resolve(&mut self, provider: &dyn SecretProvider) -> Result<(), SecretError> {
+        if let Some(name) = self.secret_name() {
+            self.resolved = Some(provider.get(name)?);
+        }
+
+        Ok(())
+    }
+}
+
+impl From<String> for ConfigValue {
+    fn from(raw: String) -> ConfigValue {
+        ConfigValue {
+            raw,
+            resolved: None,
+        }
+    }
+}
+
+impl From<&str> for ConfigValue {
+    fn from(raw: &str) -> ConfigValue {
+        ConfigValue::from(raw.to_string())
+    }
+}
+
+impl From<ConfigValue> for String {
+    fn from(value: ConfigValue) -> String {
+        value.raw
+    }
+}
+
+impl fmt::Debug for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.is_secret(), &self.resolved) {
+            (true, Some(_)) => write!(f, "\"{} (redacted)\"", self.raw),
+            _ => write!(f, "{:?}", self.raw),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    /// A new directory under the system's temporary directory
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fn-core-secrets-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_encrypted(dir: &PathBuf, plaintext: &[u8]) -> (String, String) {
+        let key_file = dir.join("key");
+        let path = dir.join("secrets.enc");
+
+        fs::write(&key_file, format!("{}\n", KEY)).unwrap();
+        fs::write(
+            &path,
+            EncryptedFileProvider::encrypt(&parse_key(KEY).unwrap(), plaintext).unwrap(),
+        )
+        .unwrap();
+
+        (
+            path.to_string_lossy().into_owned(),
+            key_file.to_string_lossy().into_owned(),
+        )
+    }
+
+    #[test]
+    fn encrypted_file_round_trip() {
+        let dir = temp_dir();
+        let (path, key_file) = write_encrypted(&dir, b"database_url: postgres://db\ntoken: abc\n");
+
+        let provider = EncryptedFileProvider::load(&path, &key_file).unwrap();
+
+        assert_eq!(provider.get("database_url").unwrap(), "postgres://db");
+        assert_eq!(provider.get("token").unwrap(), "abc");
+        assert!(match provider.get("missing") {
+            Err(SecretError::NotFound(name)) => name == "missing",
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn encrypt_uses_a_new_nonce_each_time() {
+        let key = parse_key(KEY).unwrap();
+
+        let first = EncryptedFileProvider::encrypt(&key, b"a: b").unwrap();
+        let second = EncryptedFileProvider::encrypt(&key, b"a: b").unwrap();
+
+        assert_ne!(&first[..NONCE_LEN], &second[..NONCE_LEN]);
+        assert_eq!(first.len(), NONCE_LEN + 4 + AES_256_GCM.tag_len());
+    }
+
+    #[test]
+    fn encrypt_rejects_anything_but_a_map() {
+        let key = parse_key(KEY).unwrap();
+
+        assert!(match EncryptedFileProvider::encrypt(&key, b"- a\n- b\n") {
+            Err(SecretError::ParsingError(_)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn load_rejects_the_wrong_key() {
+        let dir = temp_dir();
+        let (path, key_file) = write_encrypted(&dir, b"a: b");
+
+        fs::write(&key_file, KEY.replace("00", "ff")).unwrap();
+
+        assert!(match EncryptedFileProvider::load(&path, &key_file) {
+            Err(SecretError::DecryptionError) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn load_rejects_a_modified_file() {
+        let dir = temp_dir();
+        let (path, key_file) = write_encrypted(&dir, b"a: b");
+
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&path, &data).unwrap();
+
+        assert!(match EncryptedFileProvider::load(&path, &key_file) {
+            Err(SecretError::DecryptionError) => true,
+            _ => false,
+        });
+
+        // shorter than the nonce
+        fs::write(&path, [0u8; 4]).unwrap();
+
+        assert!(match EncryptedFileProvider::load(&path, &key_file) {
+            Err(SecretError::DecryptionError) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn parse_key_accepts_64_hex_characters() {
+        let key = parse_key(&format!("  {}\n", KEY.to_uppercase())).unwrap();
+
+        assert_eq!(key.len(), 32);
+        assert_eq!(&key[..3], &[0x00u8, 0x01, 0x02][..]);
+        assert_eq!(key[31], 0x1f);
+    }
+
+    #[test]
+    fn parse_key_rejects_invalid_keys() {
+        let invalid = vec![
+            String::new(),
+            KEY[..62].to_string(),
+            format!("{}00", KEY),
+            KEY.replace("0a", "zz"),
+            KEY.replace("0a", "+a"),
+            // 64 bytes, but not 64 characters
+            format!("é{}", &KEY[2..]),
+        ];
+
+        for key in invalid.iter() {
+            assert!(
+                match parse_key(key) {
+                    Err(SecretError::InvalidKey) => true,
+                    _ => false,
+                },
+                "{:?} should be rejected",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn generate_key_can_be_parsed() {
+        let encoded = generate_key().unwrap();
+
+        assert_eq!(encoded.len(), 64);
+        assert_eq!(parse_key(&encoded).unwrap().len(), 32);
+        assert_ne!(encoded, generate_key().unwrap());
+    }
+
+    #[test]
+    fn file_provider_reads_secrets_from_the_directory() {
+        let dir = temp_dir();
+        fs::write(dir.join("token"), "abc\r\n").unwrap();
+
+        let provider = FileProvider::new(&dir);
+
+        assert_eq!(provider.get("token").unwrap(), "abc");
+        assert!(match provider.get("missing") {
+            Err(SecretError::NotFound(name)) => name == "missing",
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn file_provider_rejects_names_outside_of_the_directory() {
+        let dir = temp_dir();
+        let secrets = dir.join("secrets");
+        fs::create_dir_all(&secrets).unwrap();
+        fs::write(dir.join("outside"), "leaked").unwrap();
+
+        let provider = FileProvider::new(&secrets);
+
+        for name in [
+            "",
+            "../outside",
+            "a/../../outside",
+            "/etc/passwd",
+            ".",
+            "..",
+            ".hidden",
+        ]
+        .iter()
+        {
+            assert!(
+                match provider.get(name) {
+                    Err(SecretError::InvalidName(_)) => true,
+                    _ => false,
+                },
+                "{:?} should be rejected",
+                name
+            );
+        }
+    }
+}
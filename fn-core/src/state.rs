@@ -1,22 +1,191 @@
+use crate::config::FunctionConfig;
 use crate::runtime::RuntimeManager;
+use crate::secrets::SecretProvider;
 use actix_web::web::Data;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::sync::Arc;
-use uuid::Uuid;
+use std::time::Instant;
 
-pub type HandleMap = HashMap<Uuid, Arc<RwLock<dyn RuntimeManager>>>;
+/// Runtimes keyed by the name of the function
+pub type HandleMap = HashMap<String, Arc<RwLock<dyn RuntimeManager>>>;
 pub type AppData = Data<State>;
 
 /// The app contains a cache for functions, this keeps functions hot and eliminates the startup penalty
 pub struct State {
     pub handles: RwLock<HandleMap>,
+    /// Resolves the secrets referenced by a function's config when its runtime is initialized
+    pub secrets: Arc<dyn SecretProvider>,
+    /// Held by name while a function's runtime is initialized
+    starting: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    /// What's known about each function by name, a function that isn't listed is cold
+    functions: RwLock<HashMap<String, FunctionInfo>>,
+    /// Configs re-read from the config file since the pod started, by name. Used instead of the
+    /// config the function's route was registered with
+    reloaded: RwLock<HashMap<String, FunctionConfig>>,
 }
 
 impl State {
-    pub fn new() -> State {
+    pub fn new(secrets: Arc<dyn SecretProvider>) -> State {
         State {
             handles: RwLock::new(HashMap::new()),
+            secrets,
+            starting: Mutex::new(HashMap::new()),
+            functions: RwLock::new(HashMap::new()),
+            reloaded: RwLock::new(HashMap::new()),
         }
     }
+
+    /// The runtime of the function, none while it's cold
+    pub fn runtime(&self, name: &str) -> Option<Arc<RwLock<dyn RuntimeManager>>> {
+        self.handles.read().get(name).cloned()
+    }
+
+    /// Lock held while the function's runtime is initialized, so that a single request starts it
+    /// while the others wait, without holding up the requests to other functions
+    pub fn start_lock(&self, name: &str) -> Arc<Mutex<()>> {
+        self.starting
+            .lock()
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    pub fn info(&self, name: &str) -> FunctionInfo {
+        self.functions.read().get(name).cloned().unwrap_or_default()
+    }
+
+    pub fn status(&self, name: &str) -> FunctionStatus {
+        self.info(name).status
+    }
+
+    /// Set the status of the function, the uptime is counted from when it becomes ready
+    pub fn set_status(&self, name: &str, status: FunctionStatus) {
+        let mut functions = self.functions.write();
+        let info = functions.entry(name.to_string()).or_default();
+
+        match status {
+            FunctionStatus::Ready if info.status != FunctionStatus::Ready => {
+                info.started = Some(Instant::now())
+            }
+            FunctionStatus::Ready => {}
+            _ => info.started = None,
+        }
+
+        info.status = status;
+    }
+
+    /// Count a request handled by the function, keeping the error when it failed
+    pub fn record_request(&self, name: &str, error: Option<&failure::Error>) {
+        let mut functions = self.functions.write();
+        let info = functions.entry(name.to_string()).or_default();
+
+        info.requests += 1;
+
+        if let Some(error) = error {
+            info.last_error = Some(error.to_string());
+        }
+    }
+
+    /// While a function is draining, new requests to it are turned away
+    pub fn set_draining(&self, name: &str, draining: bool) {
+        self.functions
+            .write()
+            .entry(name.to_string())
+            .or_default()
+            .draining = draining;
+    }
+
+    pub fn is_draining(&self, name: &str) -> bool {
+        self.info(name).draining
+    }
+
+    /// The config of the function, if it has been reloaded since the pod started
+    pub fn reloaded_config(&self, name: &str) -> Option<FunctionConfig> {
+        self.reloaded.read().get(name).cloned()
+    }
+
+    pub fn set_reloaded_config(&self, config: FunctionConfig) {
+        self.reloaded
+            .write()
+            .insert(config.name().to_string(), config);
+    }
+}
+
+/// The state of every worker, each worker has its own unless the runtimes are shared. Used to
+/// report on the whole pod, such as for readiness and metrics, rather than on the serving worker
+pub struct WorkerStates {
+    /// How many states there will be once every worker has started
+    expected: usize,
+    states: RwLock<Vec<AppData>>,
+}
+
+impl WorkerStates {
+    pub fn new(expected: usize) -> WorkerStates {
+        WorkerStates {
+            expected,
+            states: RwLock::new(vec![]),
+        }
+    }
+
+    /// Add the state of a worker as it starts
+    pub fn register(&self, state: AppData) {
+        self.states.write().push(state);
+    }
+
+    pub fn states(&self) -> Vec<AppData> {
+        self.states.read().clone()
+    }
+
+    /// Whether every worker has started
+    pub fn started(&self) -> bool {
+        self.states.read().len() >= self.expected
+    }
+
+    /// The status of the function in the first worker where it isn't ready, ready when it's ready
+    /// in all of them
+    pub fn status(&self, name: &str) -> FunctionStatus {
+        self.states
+            .read()
+            .iter()
+            .map(|state| state.status(name))
+            .find(|status| *status != FunctionStatus::Ready)
+            .unwrap_or(FunctionStatus::Ready)
+    }
+}
+
+/// The lifecycle and activity of a function's runtime
+#[derive(Clone, Debug, Default)]
+pub struct FunctionInfo {
+    pub status: FunctionStatus,
+    /// When the runtime last became ready, none unless it's ready
+    pub started: Option<Instant>,
+    /// Requests handled since the pod started
+    pub requests: u64,
+    /// Most recent error returned by the runtime
+    pub last_error: Option<String>,
+    pub draining: bool,
+}
+
+/// Where the runtime of a function is in its lifecycle
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FunctionStatus {
+    /// Not initialized yet, it will be on its first request
+    Cold,
+    /// Initializing
+    Starting,
+    /// Initialized and passing its health check, if it has one
+    Ready,
+    /// Failed to initialize, exited or failed its health check
+    Crashed,
+    /// Being restarted after it crashed
+    Restarting,
+}
+
+impl Default for FunctionStatus {
+    fn default() -> FunctionStatus {
+        FunctionStatus::Cold
+    }
 }
@@ -0,0 +1,666 @@
+//! Distributed tracing with W3C trace context (https://www.w3.org/TR/trace-context/). The trace of
+//! a request is continued from its `traceparent` header, or started when the header isn't set, and
+//! each span is exported once it ends. Spans are only recorded when an exporter is configured, the
+//! trace context is always propagated to functions.
+
+use failure::Fail;
+use parking_lot::{Mutex, RwLock};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Name of the header with the trace context
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Most spans sent to the OTLP endpoint in a single request
+const BATCH_SIZE: usize = 256;
+
+/// How long spans wait to be sent to the OTLP endpoint when the batch isn't full
+const BATCH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to wait on the OTLP endpoint before giving up on a batch
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    static ref EXPORTER: RwLock<Option<Mutex<Sender<SpanData>>>> = RwLock::new(None);
+}
+
+#[derive(Debug, Fail)]
+pub enum TracingError {
+    #[fail(
+        display = "OTLP endpoint \"{}\" is not supported, it must be an http:// URL",
+        _0
+    )]
+    UnsupportedEndpoint(String),
+}
+
+/// Where spans are exported to
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "exporter", rename_all = "snake_case")]
+pub enum TracingConfig {
+    /// Spans aren't recorded
+    None,
+    /// Each span is written to stdout as a line of JSON, in the OTLP format
+    Stdout,
+    /// Spans are sent in batches to a collector with OTLP/HTTP as JSON
+    /// (ex: http://127.0.0.1:4318/v1/traces)
+    Otlp {
+        endpoint: String,
+        #[serde(default = "TracingConfig::default_service_name")]
+        service_name: String,
+    },
+}
+
+impl Default for TracingConfig {
+    fn default() -> TracingConfig {
+        TracingConfig::None
+    }
+}
+
+impl TracingConfig {
+    pub fn default_service_name() -> String {
+        "fn-rs".to_string()
+    }
+}
+
+/// Start exporting spans, spans aren't recorded until this is called
+pub fn init(config: &TracingConfig) -> Result<(), TracingError> {
+    let (sender, receiver) = mpsc::channel::<SpanData>();
+
+    match config {
+        TracingConfig::None => return Ok(()),
+        TracingConfig::Stdout => {
+            thread::spawn(move || {
+                for span in receiver {
+                    println!("{}", span.to_json());
+                }
+            });
+        }
+        TracingConfig::Otlp {
+            endpoint,
+            service_name,
+        } => {
+            let endpoint = Endpoint::parse(endpoint)
+                .ok_or_else(|| TracingError::UnsupportedEndpoint(endpoint.clone()))?;
+            let service_name = service_name.clone();
+
+            thread::spawn(move || export_otlp(&endpoint, &service_name, receiver));
+        }
+    }
+
+    *EXPORTER.write() = Some(Mutex::new(sender));
+
+    Ok(())
+}
+
+fn is_enabled() -> bool {
+    EXPORTER.read().is_some()
+}
+
+fn export(span: SpanData) {
+    if let Some(sender) = EXPORTER.read().as_ref() {
+        let _ = sender.lock().send(span);
+    }
+}
+
+/// Identifies a span within a trace, propagated with the `traceparent` header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpanContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    /// Whether the trace is recorded, the caller may have decided not to record it
+    pub sampled: bool,
+}
+
+impl SpanContext {
+    /// Parse a `traceparent` header (ex: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01),
+    /// none if it's invalid
+    pub fn parse(traceparent: &str) -> Option<SpanContext> {
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+
+        if parts.len() < 4 || parts[0].len() != 2 || parts[0] == "ff" {
+            return None;
+        }
+
+        // later versions may add fields, but version 00 has exactly 4
+        if parts[0] == "00" && parts.len() != 4 {
+            return None;
+        }
+
+        let mut trace_id = [0u8; 16];
+        let mut span_id = [0u8; 8];
+        let mut flags = [0u8; 1];
+
+        decode_hex(parts[1], &mut trace_id)?;
+        decode_hex(parts[2], &mut span_id)?;
+        decode_hex(parts[3], &mut flags)?;
+
+        // all zeros is invalid for both ids
+        if trace_id == [0u8; 16] || span_id == [0u8; 8] {
+            return None;
+        }
+
+        Some(SpanContext {
+            trace_id,
+            span_id,
+            sampled: flags[0] & 1 == 1,
+        })
+    }
+
+    /// Format as a `traceparent` header
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.span_id),
+            if self.sampled { 1 } else { 0 }
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpanKind {
+    /// Handles a request from a client
+    Server,
+    /// An operation within the pod
+    Internal,
+}
+
+/// A timed operation within a trace, the span ends and is exported when it's dropped
+pub struct Span {
+    context: SpanContext,
+    /// None when the span isn't recorded
+    data: Option<SpanData>,
+}
+
+impl Span {
+    /// Start a span for a request from a client, continuing the trace of the caller when known
+    pub fn server(name: &str, parent: Option<SpanContext>) -> Span {
+        Span::new(name, SpanKind::Server, parent)
+    }
+
+    /// Start a span within the trace of the parent, or a new trace when there isn't one
+    pub fn start(name: &str, parent: Option<SpanContext>) -> Span {
+        Span::new(name, SpanKind::Internal, parent)
+    }
+
+    /// Start a span with the parent from a `traceparent`, used where the parent was passed along
+    /// with a FunctionContext
+    pub fn from_traceparent(name: &str, traceparent: Option<&str>) -> Span {
+        Span::start(name, traceparent.and_then(SpanContext::parse))
+    }
+
+    fn new(name: &str, kind: SpanKind, parent: Option<SpanContext>) -> Span {
+        let context = SpanContext {
+            trace_id: match parent {
+                Some(parent) => parent.trace_id,
+                None => random_id(),
+            },
+            span_id: random_id(),
+            sampled: parent.map_or(true, |parent| parent.sampled),
+        };
+
+        let data = if context.sampled && is_enabled() {
+            Some(SpanData {
+                name: name.to_string(),
+                kind,
+                context,
+                parent_span_id: parent.map(|parent| parent.span_id),
+                start: SystemTime::now(),
+                started: Instant::now(),
+                duration: Duration::from_secs(0),
+                attributes: vec![],
+                error: None,
+            })
+        } else {
+            None
+        };
+
+        Span { context, data }
+    }
+
+    pub fn context(&self) -> SpanContext {
+        self.context
+    }
+
+    pub fn set_attribute<V: ToString>(&mut self, key: &str, value: V) {
+        if let Some(data) = &mut self.data {
+            data.attributes.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    /// Mark the span as failed
+    pub fn set_error<E: ToString>(&mut self, error: E) {
+        if let Some(data) = &mut self.data {
+            data.error = Some(error.to_string());
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(mut data) = self.data.take() {
+            data.duration = data.started.elapsed();
+            export(data);
+        }
+    }
+}
+
+/// A span that has ended
+struct SpanData {
+    name: String,
+    kind: SpanKind,
+    context: SpanContext,
+    parent_span_id: Option<[u8; 8]>,
+    start: SystemTime,
+    /// Measures the duration, the system clock may change while the span is open
+    started: Instant,
+    duration: Duration,
+    attributes: Vec<(String, String)>,
+    error: Option<String>,
+}
+
+impl SpanData {
+    /// The span in the OTLP JSON format
+    fn to_json(&self) -> Value {
+        let start = self
+            .start
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_else(|_| Duration::from_secs(0));
+        let end = start + self.duration;
+
+        let attributes: Vec<Value> = self
+            .attributes
+            .iter()
+            .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+            .collect();
+
+        let status = match &self.error {
+            Some(message) => json!({ "code": 2, "message": message }),
+            None => json!({}),
+        };
+
+        let parent_span_id = match &self.parent_span_id {
+            Some(id) => encode_hex(id),
+            None => String::new(),
+        };
+
+        json!({
+            "traceId": encode_hex(&self.context.trace_id),
+            "spanId": encode_hex(&self.context.span_id),
+            "parentSpanId": parent_span_id,
+            "name": self.name,
+            "kind": match self.kind {
+                SpanKind::Internal => 1,
+                SpanKind::Server => 2,
+            },
+            "startTimeUnixNano": nanos(start).to_string(),
+            "endTimeUnixNano": nanos(end).to_string(),
+            "attributes": attributes,
+            "status": status,
+        })
+    }
+}
+
+/// Send the spans to the endpoint in batches, a batch that fails is logged and dropped
+fn export_otlp(endpoint: &Endpoint, service_name: &str, receiver: Receiver<SpanData>) {
+    loop {
+        let mut batch = vec![];
+
+        match receiver.recv() {
+            Ok(span) => batch.push(span),
+            Err(_) => return,
+        }
+
+        let deadline = Instant::now() + BATCH_TIMEOUT;
+
+        while batch.len() < BATCH_SIZE {
+            let now = Instant::now();
+
+            if now >= deadline {
+                break;
+            }
+
+            match receiver.recv_timeout(deadline - now) {
+                Ok(span) => batch.push(span),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        let body = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        { "key": "service.name", "value": { "stringValue": service_name } }
+                    ]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "fn-rs" },
+                    "spans": batch.iter().map(|span| span.to_json()).collect::<Vec<Value>>(),
+                }]
+            }]
+        });
+
+        if let Err(e) = endpoint.post(body.to_string().as_bytes()) {
+            println!(
+                "Failed to export {} spans to {}: {}",
+                batch.len(),
+                endpoint.url,
+                e
+            );
+        }
+    }
+}
+
+/// An http:// URL that spans are posted to, TLS isn't supported since the collector is expected
+/// to run alongside the pod
+struct Endpoint {
+    url: String,
+    /// host and port
+    authority: String,
+    path: String,
+}
+
+impl Endpoint {
+    fn parse(url: &str) -> Option<Endpoint> {
+        if !url.starts_with("http://") {
+            return None;
+        }
+
+        let rest = &url["http://".len()..];
+
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/v1/traces"),
+        };
+
+        if authority.is_empty() {
+            return None;
+        }
+
+        let authority = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        };
+
+        Some(Endpoint {
+            url: url.to_string(),
+            authority,
+            path: path.to_string(),
+        })
+    }
+
+    fn post(&self, body: &[u8]) -> std::io::Result<()> {
+        let mut stream = TcpStream::connect(&self.authority)?;
+
+        stream.set_read_timeout(Some(EXPORT_TIMEOUT))?;
+        stream.set_write_timeout(Some(EXPORT_TIMEOUT))?;
+
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.authority,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+
+        // HTTP/1.1 200 OK
+        let status = response.split_whitespace().nth(1).unwrap_or("");
+
+        if status.starts_with('2') {
+            Ok(())
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("collector responded with status {}", status),
+            ))
+        }
+    }
+}
+
+fn random_id<T: AsMut<[u8]> + Default>() -> T {
+    let mut id = T::default();
+
+    // an all zero id is invalid, which is also what's left if the random source fails
+    while id.as_mut().iter().all(|byte| *byte == 0) {
+        if SystemRandom::new().fill(id.as_mut()).is_err() {
+            id.as_mut()[0] = 1;
+        }
+    }
+
+    id
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode lowercase hex into the buffer, none unless the hex is exactly the length of the buffer
+fn decode_hex(hex: &str, buf: &mut [u8]) -> Option<()> {
+    if hex.len() != buf.len() * 2 || hex.chars().any(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn span_data(name: &str, error: Option<&str>) -> SpanData {
+        SpanData {
+            name: name.to_string(),
+            kind: SpanKind::Server,
+            context: SpanContext::parse(TRACEPARENT).unwrap(),
+            parent_span_id: None,
+            start: UNIX_EPOCH + Duration::from_secs(1),
+            started: Instant::now(),
+            duration: Duration::from_millis(5),
+            attributes: vec![("http.method".to_string(), "GET".to_string())],
+            error: error.map(|error| error.to_string()),
+        }
+    }
+
+    /// Accept a single request, respond with the status and return the request's body
+    fn collector(listener: TcpListener, status: &'static str) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 4096];
+
+            // the exporter doesn't shut down its side, so the body is read up to its length
+            let body_start = loop {
+                let bytes_read = stream.read(&mut buf).unwrap();
+                assert_ne!(bytes_read, 0, "the request ended before its body");
+                request.extend_from_slice(&buf[..bytes_read]);
+
+                if let Some(index) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                    break index + 4;
+                }
+            };
+
+            let head = String::from_utf8_lossy(&request[..body_start]).to_string();
+            assert!(head.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+
+            let length: usize = head
+                .lines()
+                .find(|line| line.starts_with("Content-Length: "))
+                .map(|line| line["Content-Length: ".len()..].parse().unwrap())
+                .unwrap();
+
+            while request.len() < body_start + length {
+                let bytes_read = stream.read(&mut buf).unwrap();
+                assert_ne!(bytes_read, 0, "the request ended before its body");
+                request.extend_from_slice(&buf[..bytes_read]);
+            }
+
+            write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+
+            String::from_utf8(request[body_start..].to_vec()).unwrap()
+        })
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let context = SpanContext::parse(TRACEPARENT).unwrap();
+
+        assert_eq!(
+            encode_hex(&context.trace_id),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(encode_hex(&context.span_id), "00f067aa0ba902b7");
+        assert!(context.sampled);
+        assert_eq!(context.traceparent(), TRACEPARENT);
+
+        let unsampled = SpanContext::parse(&TRACEPARENT.replace("-01", "-00")).unwrap();
+
+        assert!(!unsampled.sampled);
+        assert_eq!(
+            SpanContext::parse(&unsampled.traceparent()),
+            Some(unsampled)
+        );
+    }
+
+    #[test]
+    fn parse_rejects_uppercase_hex() {
+        assert_eq!(SpanContext::parse(&TRACEPARENT.to_uppercase()), None);
+        assert_eq!(
+            SpanContext::parse(&TRACEPARENT.replace("4bf9", "4BF9")),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_rejects_all_zero_ids() {
+        let zero_trace = "00-00000000000000000000000000000000-00f067aa0ba902b7-01";
+        let zero_span = "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01";
+
+        assert_eq!(SpanContext::parse(zero_trace), None);
+        assert_eq!(SpanContext::parse(zero_span), None);
+    }
+
+    #[test]
+    fn parse_rejects_version_ff() {
+        assert_eq!(
+            SpanContext::parse(&TRACEPARENT.replacen("00", "ff", 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_only_allows_extra_fields_after_version_00() {
+        assert_eq!(SpanContext::parse(&format!("{}-extra", TRACEPARENT)), None);
+
+        let later_version = format!("{}-extra", TRACEPARENT.replacen("00", "01", 1));
+
+        assert_eq!(
+            SpanContext::parse(&later_version),
+            SpanContext::parse(TRACEPARENT)
+        );
+    }
+
+    #[test]
+    fn parse_rejects_malformed_headers() {
+        for traceparent in &[
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902bz-01",
+            "0-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(SpanContext::parse(traceparent), None, "{}", traceparent);
+        }
+    }
+
+    #[test]
+    fn endpoint_parse() {
+        let endpoint = Endpoint::parse("http://collector:4318/v1/traces").unwrap();
+        assert_eq!(endpoint.authority, "collector:4318");
+        assert_eq!(endpoint.path, "/v1/traces");
+
+        let endpoint = Endpoint::parse("http://collector").unwrap();
+        assert_eq!(endpoint.authority, "collector:80");
+        assert_eq!(endpoint.path, "/v1/traces");
+
+        assert!(Endpoint::parse("https://collector:4318").is_none());
+        assert!(Endpoint::parse("http:///v1/traces").is_none());
+    }
+
+    #[test]
+    fn export_otlp_posts_the_batch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let collector = collector(listener, "200 OK");
+
+        let (sender, receiver) = mpsc::channel();
+        sender.send(span_data("request", None)).unwrap();
+        sender.send(span_data("invoke", Some("timed out"))).unwrap();
+        drop(sender);
+
+        // returns once the spans are sent and the sender is gone
+        export_otlp(&Endpoint::parse(&url).unwrap(), "test-service", receiver);
+
+        let body: Value = serde_json::from_str(&collector.join().unwrap()).unwrap();
+        let resource_spans = &body["resourceSpans"][0];
+
+        assert_eq!(
+            resource_spans["resource"]["attributes"][0],
+            json!({ "key": "service.name", "value": { "stringValue": "test-service" } })
+        );
+
+        let spans = resource_spans["scopeSpans"][0]["spans"].as_array().unwrap();
+
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["name"], "request");
+        assert_eq!(spans[0]["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(spans[0]["spanId"], "00f067aa0ba902b7");
+        assert_eq!(spans[0]["kind"], 2);
+        assert_eq!(spans[0]["startTimeUnixNano"], "1000000000");
+        assert_eq!(spans[0]["endTimeUnixNano"], "1005000000");
+        assert_eq!(spans[0]["status"], json!({}));
+        assert_eq!(
+            spans[0]["attributes"][0],
+            json!({ "key": "http.method", "value": { "stringValue": "GET" } })
+        );
+        assert_eq!(spans[1]["name"], "invoke");
+        assert_eq!(
+            spans[1]["status"],
+            json!({ "code": 2, "message": "timed out" })
+        );
+    }
+
+    #[test]
+    fn post_fails_on_an_error_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let collector = collector(listener, "503 Service Unavailable");
+
+        let error = Endpoint::parse(&url).unwrap().post(b"{}").unwrap_err();
+
+        assert_eq!(collector.join().unwrap(), "{}");
+        assert!(error.to_string().contains("503"));
+    }
+}
@@ -0,0 +1,1397 @@
+use crate::config::{
+    Bind, Config, FunctionConfig, HealthCheckConfig, ListenerConfig, NetworkingConfig, RouteType,
+    RuntimeStore, TlsConfig, ANY_METHOD, RUNTIMES, SUPPORTED_METHODS, TAIL_PARAM,
+};
+use failure::Fail;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// The first 8 bytes of every WebAssembly binary, the magic number `\0asm` followed by version 1
+const WASM_HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+/// A single problem found in the config. The first field of each variant is the location of the
+/// problem within the config, such as `functions[1].method`
+#[derive(Debug, Fail)]
+pub enum ValidationError {
+    #[fail(display = "{}: config must contain at least 1 function", _0)]
+    NoFunctions(String),
+
+    #[fail(display = "{}: \"{}\" is not a valid port", _0, _1)]
+    InvalidPort(String, String),
+
+    #[fail(display = "{}: host must not be empty", _0)]
+    EmptyHost(String),
+
+    #[fail(
+        display = "{}: invalid name \"{}\", names may only contain letters, numbers, \"-\", \"_\" and \".\"",
+        _0, _1
+    )]
+    InvalidName(String, String),
+
+    #[fail(display = "{}: name \"{}\" is already used by {}", _0, _1, _2)]
+    DuplicateName(String, String, String),
+
+    #[fail(display = "{}: unknown runtime \"{}\", expected one of {}", _0, _1, _2)]
+    UnknownRuntime(String, String, String),
+
+    #[fail(
+        display = "{}: unsupported HTTP method \"{}\", expected one of {}",
+        _0, _1, _2
+    )]
+    UnsupportedMethod(String, String, String),
+
+    #[fail(display = "{}: at least 1 method is required", _0)]
+    NoMethods(String),
+
+    #[fail(display = "{}: route must start with \"/\", found \"{}\"", _0, _1)]
+    InvalidRoute(String, String),
+
+    #[fail(display = "{}: invalid route \"{}\", {}", _0, _1, _2)]
+    InvalidRoutePattern(String, String, String),
+
+    #[fail(display = "{}: {} {} is already handled by {}", _0, _1, _2, _3)]
+    DuplicateRoute(String, String, String, String),
+
+    #[fail(display = "{}: handler \"{}\" does not exist", _0, _1)]
+    MissingHandler(String, String),
+
+    #[fail(display = "{}: handler \"{}\" is not a file", _0, _1)]
+    HandlerNotFile(String, String),
+
+    #[fail(
+        display = "{}: handler \"{}\" is not executable, mark it executable or set cmd",
+        _0, _1
+    )]
+    HandlerNotExecutable(String, String),
+
+    #[fail(display = "{}: failed to read handler \"{}\": {}", _0, _1, _2)]
+    UnreadableHandler(String, String, std::io::Error),
+
+    #[fail(display = "{}: \"{}\" is not a valid WebAssembly module", _0, _1)]
+    MalformedWasm(String, String),
+
+    #[fail(display = "{}: working directory \"{}\" does not exist", _0, _1)]
+    MissingCwd(String, String),
+
+    #[fail(display = "{}: streaming is not supported by the {} runtime", _0, _1)]
+    StreamingUnsupported(String, String),
+
+    #[fail(
+        display = "{}: websocket routes are opened with a GET, found {}",
+        _0, _1
+    )]
+    WebSocketMethod(String, String),
+
+    #[fail(
+        display = "{}: health checks are not supported by the {} runtime",
+        _0, _1
+    )]
+    HealthCheckUnsupported(String, String),
+
+    #[fail(display = "{}: invalid health check, {}", _0, _1)]
+    InvalidHealthCheck(String, String),
+
+    #[fail(
+        display = "{}: \"{}\" must start with \"/\" and can't be the root",
+        _0, _1
+    )]
+    InvalidAdminPrefix(String, String),
+
+    #[fail(display = "{}: token must not be empty", _0)]
+    EmptyAdminToken(String),
+
+    #[fail(
+        display = "{}: the admin API requires gateway.runtime_store to be shared when there is \
+                   more than 1 worker",
+        _0
+    )]
+    AdminRequiresSharedRuntimes(String),
+
+    #[fail(display = "{}: at least 1 worker is required", _0)]
+    NoWorkers(String),
+
+    #[fail(display = "{}: \"{}\" does not exist", _0, _1)]
+    MissingTlsFile(String, String),
+
+    #[fail(display = "{}: port is already used by {}", _0, _1)]
+    RedirectPortInUse(String, String),
+
+    #[fail(display = "{}: invalid TLS config, {}", _0, _1)]
+    InvalidTls(String, String),
+
+    #[fail(display = "{}: invalid listener, {}", _0, _1)]
+    InvalidListener(String, String),
+
+    #[fail(display = "{}: at least 1 listener must not be internal", _0)]
+    NoPublicListener(String),
+}
+
+impl Config {
+    /// Check the config for every problem that would otherwise surface as a panic during startup
+    /// or as an error during the first request to a function. All of the problems are collected,
+    /// rather than stopping at the first, so that they can be fixed in one pass.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+
+        validate_networking(self, &mut errors);
+        validate_admin(self, &mut errors);
+
+        if self.functions().is_empty() {
+            errors.push(ValidationError::NoFunctions("functions".to_string()));
+        }
+
+        // (method, route) -> location of the first function that handles it
+        let mut routes: HashMap<(String, String), String> = HashMap::new();
+        // name -> location of the first function with that name
+        let mut names: HashMap<&str, String> = HashMap::new();
+
+        for (index, func) in self.functions_iter().enumerate() {
+            let location = |field: &str| format!("functions[{}].{}", index, field);
+
+            if !is_valid_name(func.name()) {
+                errors.push(ValidationError::InvalidName(
+                    location("name"),
+                    func.name().to_string(),
+                ));
+            }
+
+            match names.get(func.name()) {
+                Some(first) => errors.push(ValidationError::DuplicateName(
+                    location("name"),
+                    func.name().to_string(),
+                    first.clone(),
+                )),
+                None => {
+                    names.insert(func.name(), format!("functions[{}]", index));
+                }
+            }
+
+            if !RUNTIMES.contains(&func.runtime.as_str()) {
+                errors.push(ValidationError::UnknownRuntime(
+                    location("runtime"),
+                    func.runtime.clone(),
+                    RUNTIMES.join(", "),
+                ));
+            }
+
+            if !func.route.starts_with('/') {
+                errors.push(ValidationError::InvalidRoute(
+                    location("route"),
+                    func.route.clone(),
+                ));
+            }
+
+            validate_route_params(&func.route, &location("route"), &mut errors);
+
+            let declared = func.method.declared();
+
+            if declared.is_empty() {
+                errors.push(ValidationError::NoMethods(location("method")));
+            }
+
+            for method in declared {
+                let uppercase = method.to_uppercase();
+
+                if method != ANY_METHOD && !SUPPORTED_METHODS.contains(&uppercase.as_str()) {
+                    errors.push(ValidationError::UnsupportedMethod(
+                        location("method"),
+                        method.to_string(),
+                        SUPPORTED_METHODS.join(", "),
+                    ));
+                }
+            }
+
+            for method in func.method.list() {
+                if !SUPPORTED_METHODS.contains(&method.as_str()) {
+                    continue;
+                }
+
+                let key = (method.clone(), func.route.clone());
+
+                match routes.get(&key) {
+                    Some(first) => errors.push(ValidationError::DuplicateRoute(
+                        location("route"),
+                        method,
+                        func.route.clone(),
+                        first.clone(),
+                    )),
+                    None => {
+                        routes.insert(key, format!("functions[{}]", index));
+                    }
+                }
+            }
+
+            validate_handler(func, &location("handler"), &mut errors);
+
+            if let Some(cwd) = &func.cwd {
+                if !Path::new(cwd).is_dir() {
+                    errors.push(ValidationError::MissingCwd(location("cwd"), cwd.clone()));
+                }
+            }
+
+            if func.stream_body && func.runtime != "unix_socket" {
+                errors.push(ValidationError::StreamingUnsupported(
+                    location("stream_body"),
+                    func.runtime.clone(),
+                ));
+            }
+
+            if func.stream_response && func.runtime != "unix_socket" {
+                errors.push(ValidationError::StreamingUnsupported(
+                    location("stream_response"),
+                    func.runtime.clone(),
+                ));
+            }
+
+            if func.route_type == RouteType::WebSocket && func.method.list() != ["GET"] {
+                errors.push(ValidationError::WebSocketMethod(
+                    location("method"),
+                    func.method.list().join(", "),
+                ));
+            }
+
+            if let Some(check) = &func.health_check {
+                validate_health_check(func, check, &location("health_check"), &mut errors);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Check the parameters of the route the same way that the router parses them, a parameter runs
+/// from `{` to the next `}` and may have a regex after the name
+fn validate_route_params(route: &str, location: &str, errors: &mut Vec<ValidationError>) {
+    let invalid = |reason: String| {
+        ValidationError::InvalidRoutePattern(location.to_string(), route.to_string(), reason)
+    };
+
+    // a "*" within a parameter is part of its regex
+    let mut in_param = false;
+    let wildcard = route.char_indices().find(|&(_, c)| {
+        match c {
+            '{' => in_param = true,
+            '}' => in_param = false,
+            _ => {}
+        }
+
+        c == '*' && !in_param
+    });
+
+    if let Some((index, _)) = wildcard {
+        if index != route.len() - 1 || !route.ends_with("/*") {
+            errors.push(invalid(
+                "\"*\" is only allowed as the last segment of the route".to_string(),
+            ));
+        }
+    }
+
+    let mut names: Vec<&str> = vec![];
+    let mut rest = route;
+
+    while let Some(start) = rest.find('{') {
+        if rest[..start].contains('}') {
+            errors.push(invalid("\"}\" is never opened".to_string()));
+            return;
+        }
+
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => {
+                errors.push(invalid("\"{\" is never closed".to_string()));
+                return;
+            }
+        };
+
+        let param = &rest[start + 1..end];
+
+        let (name, pattern) = match param.find(':') {
+            Some(colon) => (&param[..colon], Some(&param[colon + 1..])),
+            None => (param, None),
+        };
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            errors.push(invalid(format!(
+                "\"{}\" is not a valid parameter name",
+                name
+            )));
+        } else if names.contains(&name) || (name == TAIL_PARAM && route.ends_with('*')) {
+            errors.push(invalid(format!(
+                "parameter \"{}\" is used more than once",
+                name
+            )));
+        } else {
+            names.push(name);
+        }
+
+        if let Some(pattern) = pattern {
+            if let Err(e) = Regex::new(pattern) {
+                errors.push(invalid(format!(
+                    "the pattern of parameter \"{}\" is not a valid regex: {}",
+                    name, e
+                )));
+            }
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    if rest.contains('}') {
+        errors.push(invalid("\"}\" is never opened".to_string()));
+    }
+}
+
+fn validate_health_check(
+    func: &FunctionConfig,
+    check: &HealthCheckConfig,
+    location: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    let invalid =
+        |reason: String| ValidationError::InvalidHealthCheck(location.to_string(), reason);
+
+    if func.runtime != "unix_socket" {
+        errors.push(ValidationError::HealthCheckUnsupported(
+            location.to_string(),
+            func.runtime.clone(),
+        ));
+    }
+
+    // the check is sent as a single request, and its whole response is read
+    if func.route_type == RouteType::WebSocket {
+        errors.push(invalid("websocket routes can't be checked".to_string()));
+    }
+
+    if func.stream_body || func.stream_response {
+        errors.push(invalid(
+            "functions that stream their body or response can't be checked".to_string(),
+        ));
+    }
+
+    if !check.path.starts_with('/') {
+        errors.push(invalid(format!(
+            "path must start with \"/\", found \"{}\"",
+            check.path
+        )));
+    }
+
+    if !SUPPORTED_METHODS.contains(&check.method.to_uppercase().as_str()) {
+        errors.push(invalid(format!(
+            "unsupported HTTP method \"{}\", expected one of {}",
+            check.method,
+            SUPPORTED_METHODS.join(", ")
+        )));
+    }
+
+    if check.interval_secs == 0 {
+        errors.push(invalid("interval_secs must be at least 1".to_string()));
+    }
+}
+
+fn validate_admin(config: &Config, errors: &mut Vec<ValidationError>) {
+    let admin = config.admin();
+
+    if !admin.prefix.starts_with('/') || admin.prefix.trim_end_matches('/').is_empty() {
+        errors.push(ValidationError::InvalidAdminPrefix(
+            "admin.prefix".to_string(),
+            admin.prefix.clone(),
+        ));
+    }
+
+    if let Some(token) = &admin.token {
+        if token.value().trim().is_empty() {
+            errors.push(ValidationError::EmptyAdminToken("admin.token".to_string()));
+        }
+
+        // the admin API acts on the runtimes of the worker that serves the request, with per
+        // worker runtimes it would only restart, evict or drain the function in one of the workers
+        if config.networking().workers > 1
+            && config.gateway().runtime_store == RuntimeStore::PerWorker
+        {
+            errors.push(ValidationError::AdminRequiresSharedRuntimes(
+                "admin.token".to_string(),
+            ));
+        }
+    }
+}
+
+fn validate_networking(config: &Config, errors: &mut Vec<ValidationError>) {
+    let networking = config.networking();
+
+    if networking.host.trim().is_empty() {
+        errors.push(ValidationError::EmptyHost("networking.host".to_string()));
+    }
+
+    if networking.workers == 0 {
+        errors.push(ValidationError::NoWorkers("networking.workers".to_string()));
+    }
+
+    match networking.port.parse::<u16>() {
+        Ok(port) if port > 0 => {}
+        _ => errors.push(ValidationError::InvalidPort(
+            "networking.port".to_string(),
+            networking.port.clone(),
+        )),
+    }
+
+    validate_listeners(config, errors);
+
+    if let Some(tls) = &networking.tls {
+        validate_tls(tls, networking, errors);
+    }
+}
+
+fn validate_listeners(config: &Config, errors: &mut Vec<ValidationError>) {
+    let listeners = &config.networking().listeners;
+
+    if !listeners.is_empty() && listeners.iter().all(|listener| listener.internal) {
+        errors.push(ValidationError::NoPublicListener(
+            "networking.listeners".to_string(),
+        ));
+    }
+
+    // bind -> location of the first listener bound to it
+    let mut binds: Vec<(&Bind, String)> = vec![];
+
+    for (index, listener) in listeners.iter().enumerate() {
+        let location = format!("networking.listeners[{}]", index);
+        let invalid = |reason: String| ValidationError::InvalidListener(location.clone(), reason);
+
+        if let Some(reason) = invalid_bind(listener) {
+            errors.push(invalid(reason));
+        }
+
+        match binds.iter().find(|(bind, _)| *bind == &listener.bind) {
+            Some((_, first)) => errors.push(invalid(format!("already used by {}", first))),
+            None => binds.push((&listener.bind, location.clone())),
+        }
+
+        // a single worker serves the internal listeners, it only sees the runtimes of the other
+        // workers when they're shared
+        if listener.internal && config.gateway().runtime_store != RuntimeStore::Shared {
+            errors.push(invalid(
+                "internal listeners require gateway.runtime_store to be shared".to_string(),
+            ));
+        }
+    }
+
+    let unnamed_systemd = listeners
+        .iter()
+        .filter(|listener| listener.bind == Bind::Systemd { name: None })
+        .count();
+    let systemd = listeners
+        .iter()
+        .filter(|listener| match listener.bind {
+            Bind::Systemd { .. } => true,
+            _ => false,
+        })
+        .count();
+
+    if unnamed_systemd > 0 && systemd > 1 {
+        errors.push(ValidationError::InvalidListener(
+            "networking.listeners".to_string(),
+            "a systemd listener without a name uses every socket, so it can't be combined with \
+             other systemd listeners"
+                .to_string(),
+        ));
+    }
+}
+
+/// Why the listener can't be bound, none if it can be
+fn invalid_bind(listener: &ListenerConfig) -> Option<String> {
+    match &listener.bind {
+        Bind::Tcp { address } => {
+            let (host, port) = match address.rfind(':') {
+                Some(index) => (&address[..index], &address[index + 1..]),
+                None => return Some(format!("\"{}\" is missing a port", address)),
+            };
+
+            match port.parse::<u16>() {
+                Ok(port) if port > 0 => {}
+                _ => return Some(format!("\"{}\" is not a valid port", port)),
+            }
+
+            if host.is_empty() {
+                return Some(format!("\"{}\" is missing a host", address));
+            }
+
+            // an IPv6 address without brackets would be split at the wrong colon
+            if host.contains(':') && !(host.starts_with('[') && host.ends_with(']')) {
+                return Some(format!(
+                    "IPv6 addresses must be in brackets, found \"{}\"",
+                    address
+                ));
+            }
+
+            None
+        }
+        Bind::Unix { path } => match Path::new(path).parent() {
+            _ if path.is_empty() => Some("path must not be empty".to_string()),
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                Some(format!("directory \"{}\" does not exist", dir.display()))
+            }
+            _ => None,
+        },
+        Bind::Systemd { name: Some(name) } if name.is_empty() => {
+            Some("name must not be empty".to_string())
+        }
+        Bind::Systemd { .. } => None,
+    }
+}
+
+/// The certificate and key are only parsed when the server starts, so this only checks that the
+/// files exist
+fn validate_tls(tls: &TlsConfig, networking: &NetworkingConfig, errors: &mut Vec<ValidationError>) {
+    let files = [
+        ("networking.tls.cert", Some(&tls.cert)),
+        ("networking.tls.key", Some(&tls.key)),
+        ("networking.tls.client_ca", tls.client_ca.as_ref()),
+    ];
+
+    for (location, path) in files.iter() {
+        if let Some(path) = path {
+            if !Path::new(path).is_file() {
+                errors.push(ValidationError::MissingTlsFile(
+                    location.to_string(),
+                    path.to_string(),
+                ));
+            }
+        }
+    }
+
+    if tls.reload_interval_secs == 0 {
+        errors.push(ValidationError::InvalidTls(
+            "networking.tls.reload_interval_secs".to_string(),
+            "must be at least 1".to_string(),
+        ));
+    }
+
+    if let Some(redirect_port) = &tls.redirect_port {
+        let location = "networking.tls.redirect_port".to_string();
+
+        let redirect = match redirect_port.parse::<u16>() {
+            Ok(redirect) if redirect > 0 => redirect,
+            _ => {
+                errors.push(ValidationError::InvalidPort(
+                    location,
+                    redirect_port.clone(),
+                ));
+                return;
+            }
+        };
+
+        let listeners = networking.listeners();
+
+        // the redirect is served on the host of each public TCP listener
+        if !listeners
+            .iter()
+            .any(|listener| !listener.internal && listener.bind.tcp_host_port().is_some())
+        {
+            errors.push(ValidationError::InvalidTls(
+                location.clone(),
+                "the redirect requires a TCP listener that isn't internal".to_string(),
+            ));
+        }
+
+        let in_use = listeners.iter().position(|listener| {
+            listener.bind.tcp_host_port().map(|(_, port)| port) == Some(redirect)
+        });
+
+        if let Some(index) = in_use {
+            // without listeners the server listens on networking.host and networking.port
+            let used_by = if networking.listeners.is_empty() {
+                "networking.port".to_string()
+            } else {
+                format!("networking.listeners[{}]", index)
+            };
+
+            errors.push(ValidationError::RedirectPortInUse(location, used_by));
+        }
+    }
+}
+
+/// The handler must exist for every runtime. Handlers executed directly by the unix socket runtime
+/// must also be executable, and handlers for the wasm runtime must be WebAssembly modules.
+fn validate_handler(func: &FunctionConfig, location: &str, errors: &mut Vec<ValidationError>) {
+    let path = Path::new(&func.handler);
+
+    let metadata = match path.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => {
+            errors.push(ValidationError::MissingHandler(
+                location.to_string(),
+                func.handler.clone(),
+            ));
+            return;
+        }
+    };
+
+    if !metadata.is_file() {
+        errors.push(ValidationError::HandlerNotFile(
+            location.to_string(),
+            func.handler.clone(),
+        ));
+        return;
+    }
+
+    match func.runtime.as_str() {
+        "unix_socket" if func.cmd.is_none() => {
+            if metadata.permissions().mode() & 0o111 == 0 {
+                errors.push(ValidationError::HandlerNotExecutable(
+                    location.to_string(),
+                    func.handler.clone(),
+                ));
+            }
+        }
+        "wasm" => {
+            let mut header = [0u8; 8];
+
+            let read = File::open(path).and_then(|mut file| file.read_exact(&mut header));
+
+            match read {
+                Ok(_) if header == WASM_HEADER => {}
+                Ok(_) => errors.push(ValidationError::MalformedWasm(
+                    location.to_string(),
+                    func.handler.clone(),
+                )),
+                Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => errors.push(
+                    ValidationError::MalformedWasm(location.to_string(), func.handler.clone()),
+                ),
+                Err(e) => errors.push(ValidationError::UnreadableHandler(
+                    location.to_string(),
+                    func.handler.clone(),
+                    e,
+                )),
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The problems found in the config, as they are reported
+    fn problems(yaml: &str) -> Vec<String> {
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+
+        match config.validate() {
+            Ok(()) => vec![],
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    /// A config with the functions, which are each written by `function`
+    fn config(functions: &[String]) -> String {
+        format!(
+            "networking:\n  host: 127.0.0.1\n  port: \"8080\"\nfunctions:\n{}",
+            functions.concat()
+        )
+    }
+
+    /// A valid function, with some of its fields replaced or added
+    fn function(fields: &[(&str, &str)]) -> String {
+        let mut all = vec![
+            ("name", "hello"),
+            ("method", "GET"),
+            ("route", "/hello"),
+            ("handler", "/bin/sh"),
+            ("runtime", "unix_socket"),
+        ];
+
+        for (key, value) in fields {
+            match all.iter_mut().find(|(field, _)| field == key) {
+                Some(field) => field.1 = value,
+                None => all.push((key, value)),
+            }
+        }
+
+        all.iter()
+            .enumerate()
+            .map(|(i, (key, value))| {
+                let indent = if i == 0 { "  - " } else { "    " };
+                format!("{}{}: {}\n", indent, key, value)
+            })
+            .collect()
+    }
+
+    /// A file starting with the header of a WebAssembly module, which is all that's checked
+    fn wasm_handler(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "fn-validation-{}-{}.wasm",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, &WASM_HEADER).unwrap();
+
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn valid_config() {
+        let wasm = wasm_handler("valid");
+        let functions = [
+            function(&[]),
+            function(&[
+                ("name", "wasm"),
+                ("route", "/wasm"),
+                ("handler", &wasm),
+                ("runtime", "wasm"),
+            ]),
+            function(&[("name", "node"), ("route", "/node"), ("cmd", "node")]),
+        ];
+
+        assert_eq!(problems(&config(&functions)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn no_functions() {
+        assert_eq!(
+            problems("networking:\n  host: 127.0.0.1\n  port: \"8080\"\nfunctions: []\n"),
+            vec!["functions: config must contain at least 1 function"]
+        );
+    }
+
+    #[test]
+    fn invalid_port() {
+        for port in &["http", "0", "65536"] {
+            let yaml = config(&[function(&[])]).replace("\"8080\"", port);
+
+            assert_eq!(
+                problems(&yaml),
+                vec![format!("networking.port: \"{}\" is not a valid port", port)]
+            );
+        }
+    }
+
+    #[test]
+    fn empty_host() {
+        let yaml = config(&[function(&[])]).replace("127.0.0.1", "\" \"");
+
+        assert_eq!(
+            problems(&yaml),
+            vec!["networking.host: host must not be empty"]
+        );
+    }
+
+    #[test]
+    fn unknown_runtime() {
+        assert_eq!(
+            problems(&config(&[function(&[("runtime", "python")])])),
+            vec![
+                "functions[0].runtime: unknown runtime \"python\", expected one of unix_socket, \
+                 wasm"
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_method() {
+        assert_eq!(
+            problems(&config(&[function(&[("method", "FETCH")])])),
+            vec![format!(
+                "functions[0].method: unsupported HTTP method \"FETCH\", expected one of {}",
+                SUPPORTED_METHODS.join(", ")
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_route() {
+        assert_eq!(
+            problems(&config(&[function(&[("route", "hello")])])),
+            vec!["functions[0].route: route must start with \"/\", found \"hello\""]
+        );
+    }
+
+    #[test]
+    fn duplicate_route() {
+        let functions = [
+            function(&[]),
+            function(&[("name", "other"), ("method", "get")]),
+        ];
+
+        assert_eq!(
+            problems(&config(&functions)),
+            vec!["functions[1].route: GET /hello is already handled by functions[0]"]
+        );
+    }
+
+    #[test]
+    fn missing_handler() {
+        assert_eq!(
+            problems(&config(&[function(&[("handler", "missing.sh")])])),
+            vec!["functions[0].handler: handler \"missing.sh\" does not exist"]
+        );
+    }
+
+    #[test]
+    fn handler_not_file() {
+        assert_eq!(
+            problems(&config(&[function(&[("handler", "src")])])),
+            vec!["functions[0].handler: handler \"src\" is not a file"]
+        );
+    }
+
+    #[test]
+    fn handler_not_executable() {
+        assert_eq!(
+            problems(&config(&[function(&[("handler", "Cargo.toml")])])),
+            vec![
+                "functions[0].handler: handler \"Cargo.toml\" is not executable, mark it \
+                 executable or set cmd"
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_wasm() {
+        let empty = std::env::temp_dir().join(format!("fn-validation-{}", std::process::id()));
+        std::fs::write(&empty, b"").unwrap();
+        let empty = empty.to_string_lossy().to_string();
+
+        for handler in &["Cargo.toml", empty.as_str()] {
+            assert_eq!(
+                problems(&config(&[function(&[
+                    ("handler", handler),
+                    ("runtime", "wasm")
+                ])])),
+                vec![format!(
+                    "functions[0].handler: \"{}\" is not a valid WebAssembly module",
+                    handler
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn unreadable_handler() {
+        // a regular file, but reading the start of the address space fails
+        let problems = problems(&config(&[function(&[
+            ("handler", "/proc/self/mem"),
+            ("runtime", "wasm"),
+        ])]));
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0]
+            .starts_with("functions[0].handler: failed to read handler \"/proc/self/mem\": "));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let functions = [
+            function(&[("runtime", "python"), ("route", "hello")]),
+            function(&[("name", "missing"), ("handler", "missing.sh")]),
+        ];
+        let yaml = config(&functions)
+            .replace("127.0.0.1", "\"\"")
+            .replace("\"8080\"", "http");
+
+        assert_eq!(
+            problems(&yaml),
+            vec![
+                "networking.host: host must not be empty",
+                "networking.port: \"http\" is not a valid port",
+                "functions[0].runtime: unknown runtime \"python\", expected one of unix_socket, \
+                 wasm",
+                "functions[0].route: route must start with \"/\", found \"hello\"",
+                "functions[1].handler: handler \"missing.sh\" does not exist",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_name() {
+        assert_eq!(
+            problems(&config(&[function(&[("name", "hello world")])])),
+            vec![
+                "functions[0].name: invalid name \"hello world\", names may only contain \
+                 letters, numbers, \"-\", \"_\" and \".\""
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let functions = [function(&[]), function(&[("route", "/other")])];
+
+        assert_eq!(
+            problems(&config(&functions)),
+            vec!["functions[1].name: name \"hello\" is already used by functions[0]"]
+        );
+    }
+
+    #[test]
+    fn missing_cwd() {
+        assert_eq!(
+            problems(&config(&[function(&[("cwd", "missing")])])),
+            vec!["functions[0].cwd: working directory \"missing\" does not exist"]
+        );
+    }
+
+    #[test]
+    fn methods() {
+        let functions = [
+            function(&[("method", "[get, POST]")]),
+            function(&[("name", "any"), ("method", "\"*\""), ("route", "/any")]),
+        ];
+
+        assert_eq!(problems(&config(&functions)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn no_methods() {
+        assert_eq!(
+            problems(&config(&[function(&[("method", "[]")])])),
+            vec!["functions[0].method: at least 1 method is required"]
+        );
+    }
+
+    #[test]
+    fn duplicate_route_in_a_list_of_methods() {
+        let functions = [
+            function(&[("method", "[GET, POST]")]),
+            function(&[("name", "any"), ("method", "\"*\"")]),
+        ];
+
+        assert_eq!(
+            problems(&config(&functions)),
+            vec![
+                "functions[1].route: GET /hello is already handled by functions[0]",
+                "functions[1].route: POST /hello is already handled by functions[0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn route_params() {
+        let routes = [
+            "/users/{id}/posts/{post_id:\\d+}",
+            "/static/*",
+            "/users/{id:[a-z]*}",
+            "/files/{rest:.*}",
+            "/files/{name:[a-z]*}/*",
+        ];
+
+        for route in routes.iter() {
+            let route = format!("'{}'", route);
+
+            assert_eq!(
+                problems(&config(&[function(&[("route", &route)])])),
+                Vec::<String>::new(),
+                "{}",
+                route
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_route_params() {
+        let routes = [
+            (
+                "/static/*/more",
+                "\"*\" is only allowed as the last segment of the route",
+            ),
+            (
+                "/static*",
+                "\"*\" is only allowed as the last segment of the route",
+            ),
+            (
+                "/users/{id:[a-z]}*",
+                "\"*\" is only allowed as the last segment of the route",
+            ),
+            ("/users/{id", "\"{\" is never closed"),
+            ("/users/id}", "\"}\" is never opened"),
+            ("/users/{}", "\"\" is not a valid parameter name"),
+            (
+                "/users/{user-id}",
+                "\"user-id\" is not a valid parameter name",
+            ),
+            (
+                "/users/{id}/{id}",
+                "parameter \"id\" is used more than once",
+            ),
+            (
+                "/users/{tail}/*",
+                "parameter \"tail\" is used more than once",
+            ),
+        ];
+
+        for (route, reason) in routes.iter() {
+            assert_eq!(
+                problems(&config(&[function(&[("route", &format!("'{}'", route))])])),
+                vec![format!(
+                    "functions[0].route: invalid route \"{}\", {}",
+                    route, reason
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_route_param_regex() {
+        let problems = problems(&config(&[function(&[("route", "'/users/{id:(}'")])]));
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with(
+            "functions[0].route: invalid route \"/users/{id:(}\", the pattern of parameter \"id\" \
+             is not a valid regex: "
+        ));
+    }
+
+    #[test]
+    fn streaming_body_unsupported() {
+        let wasm = wasm_handler("stream-body");
+
+        assert_eq!(
+            problems(&config(&[function(&[
+                ("handler", &wasm),
+                ("runtime", "wasm"),
+                ("stream_body", "true"),
+            ])])),
+            vec!["functions[0].stream_body: streaming is not supported by the wasm runtime"]
+        );
+    }
+
+    #[test]
+    fn streaming_response_unsupported() {
+        let wasm = wasm_handler("stream-response");
+
+        assert_eq!(
+            problems(&config(&[function(&[
+                ("handler", &wasm),
+                ("runtime", "wasm"),
+                ("stream_response", "true"),
+            ])])),
+            vec!["functions[0].stream_response: streaming is not supported by the wasm runtime"]
+        );
+    }
+
+    #[test]
+    fn websocket_method() {
+        assert_eq!(
+            problems(&config(&[function(&[
+                ("type", "websocket"),
+                ("method", "POST")
+            ])])),
+            vec!["functions[0].method: websocket routes are opened with a GET, found POST"]
+        );
+
+        assert!(problems(&config(&[function(&[("type", "websocket")])])).is_empty());
+    }
+
+    #[test]
+    fn invalid_admin_prefix() {
+        for prefix in &["admin", "/", "//"] {
+            let yaml = format!(
+                "{}admin:\n  prefix: \"{}\"\n",
+                config(&[function(&[])]),
+                prefix
+            );
+
+            assert_eq!(
+                problems(&yaml),
+                vec![format!(
+                    "admin.prefix: \"{}\" must start with \"/\" and can't be the root",
+                    prefix
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn empty_admin_token() {
+        let yaml = format!("{}admin:\n  token: \" \"\n", config(&[function(&[])]));
+
+        assert_eq!(
+            problems(&yaml),
+            vec!["admin.token: token must not be empty"]
+        );
+    }
+
+    #[test]
+    fn health_check() {
+        let checked = |fields: &[(&str, &str)]| {
+            let check = "\n      path: /health\n      interval_secs: 5";
+            let mut fields = fields.to_vec();
+            fields.push(("health_check", check));
+
+            problems(&config(&[function(&fields)]))
+        };
+
+        assert!(checked(&[]).is_empty());
+
+        let wasm = wasm_handler("health-check");
+        assert_eq!(
+            checked(&[("handler", &wasm), ("runtime", "wasm")]),
+            vec!["functions[0].health_check: health checks are not supported by the wasm runtime"]
+        );
+
+        assert_eq!(
+            checked(&[("type", "websocket")]),
+            vec![
+                "functions[0].health_check: invalid health check, websocket routes can't be \
+                 checked"
+            ]
+        );
+
+        for field in &["stream_body", "stream_response"] {
+            assert_eq!(
+                checked(&[(field, "true")]),
+                vec![
+                    "functions[0].health_check: invalid health check, functions that stream \
+                     their body or response can't be checked"
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_health_check() {
+        let check = "\n      path: health\n      method: FETCH\n      interval_secs: 0";
+
+        assert_eq!(
+            problems(&config(&[function(&[("health_check", check)])])),
+            vec![
+                "functions[0].health_check: invalid health check, path must start with \"/\", \
+                 found \"health\"",
+                "functions[0].health_check: invalid health check, unsupported HTTP method \
+                 \"FETCH\", expected one of GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS, TRACE",
+                "functions[0].health_check: invalid health check, interval_secs must be at least 1",
+            ]
+        );
+    }
+
+    /// The config with the number of workers set
+    fn workers(yaml: &str, workers: usize) -> String {
+        yaml.replace(
+            "port: \"8080\"",
+            &format!("port: \"8080\"\n  workers: {}", workers),
+        )
+    }
+
+    #[test]
+    fn no_workers() {
+        assert_eq!(
+            problems(&workers(&config(&[function(&[])]), 0)),
+            vec!["networking.workers: at least 1 worker is required"]
+        );
+    }
+
+    #[test]
+    fn admin_requires_shared_runtimes() {
+        let admin = format!("{}admin:\n  token: secret\n", config(&[function(&[])]));
+        let shared = format!("{}gateway:\n  runtime_store: shared\n", admin);
+
+        assert_eq!(
+            problems(&workers(&admin, 2)),
+            vec![
+                "admin.token: the admin API requires gateway.runtime_store to be shared when \
+                 there is more than 1 worker"
+            ]
+        );
+
+        assert!(problems(&workers(&admin, 1)).is_empty());
+        assert!(problems(&workers(&shared, 2)).is_empty());
+    }
+
+    /// A config with TLS, `tls` and `listeners` are added under networking
+    fn tls(tls: &str, listeners: &str) -> String {
+        let file = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+        config(&[function(&[])]).replace(
+            "port: \"8080\"",
+            &format!(
+                "port: \"8080\"\n  tls:\n    cert: {}\n    key: {}\n{}{}",
+                file, file, tls, listeners
+            ),
+        )
+    }
+
+    #[test]
+    fn valid_tls() {
+        assert_eq!(problems(&tls("", "")), Vec::<String>::new());
+        assert_eq!(
+            problems(&tls("    redirect_port: \"8081\"\n", "")),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn missing_tls_file() {
+        assert_eq!(
+            problems(&tls("    client_ca: /missing/ca.crt\n", "")),
+            vec!["networking.tls.client_ca: \"/missing/ca.crt\" does not exist"]
+        );
+    }
+
+    #[test]
+    fn invalid_tls() {
+        assert_eq!(
+            problems(&tls("    reload_interval_secs: 0\n", "")),
+            vec!["networking.tls.reload_interval_secs: invalid TLS config, must be at least 1"]
+        );
+
+        assert_eq!(
+            problems(&tls("    redirect_port: \"0\"\n", "")),
+            vec!["networking.tls.redirect_port: \"0\" is not a valid port"]
+        );
+
+        let unix = "  listeners:\n    - type: unix\n      path: /tmp/fn.sock\n";
+
+        assert_eq!(
+            problems(&tls("    redirect_port: \"8081\"\n", unix)),
+            vec![
+                "networking.tls.redirect_port: invalid TLS config, the redirect requires a TCP \
+                 listener that isn't internal"
+            ]
+        );
+    }
+
+    #[test]
+    fn redirect_port_in_use() {
+        assert_eq!(
+            problems(&tls("    redirect_port: \"8080\"\n", "")),
+            vec!["networking.tls.redirect_port: port is already used by networking.port"]
+        );
+
+        let listeners = "  listeners:\n    - type: tcp\n      address: 127.0.0.1:8443\n    - \
+                         type: tcp\n      address: \"[::1]:8081\"\n";
+
+        assert_eq!(
+            problems(&tls("    redirect_port: \"8081\"\n", listeners)),
+            vec!["networking.tls.redirect_port: port is already used by networking.listeners[1]"]
+        );
+    }
+
+    /// A config listening on the listeners, each written as a YAML flow mapping
+    fn listeners(listeners: &[&str]) -> String {
+        let listeners: String = listeners
+            .iter()
+            .map(|listener| format!("    - {}\n", listener))
+            .collect();
+
+        config(&[function(&[])]).replace(
+            "port: \"8080\"",
+            &format!("port: \"8080\"\n  listeners:\n{}", listeners),
+        )
+    }
+
+    #[test]
+    fn valid_listeners() {
+        let yaml = listeners(&[
+            "{type: tcp, address: 127.0.0.1:8080}",
+            "{type: tcp, address: \"[::1]:8080\"}",
+            "{type: unix, path: /tmp/fn.sock}",
+            "{type: systemd, name: web}",
+            "{type: systemd, name: admin, internal: true}",
+        ]);
+
+        assert_eq!(
+            problems(&format!("{}gateway:\n  runtime_store: shared\n", yaml)),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn invalid_listener() {
+        let yaml = listeners(&[
+            "{type: tcp, address: 127.0.0.1}",
+            "{type: tcp, address: \"127.0.0.1:0\"}",
+            "{type: tcp, address: \":8080\"}",
+            "{type: tcp, address: \"::1:8080\"}",
+            "{type: unix, path: \"\"}",
+            "{type: unix, path: /missing/fn.sock}",
+            "{type: systemd, name: \"\"}",
+        ]);
+
+        assert_eq!(
+            problems(&yaml),
+            vec![
+                "networking.listeners[0]: invalid listener, \"127.0.0.1\" is missing a port",
+                "networking.listeners[1]: invalid listener, \"0\" is not a valid port",
+                "networking.listeners[2]: invalid listener, \":8080\" is missing a host",
+                "networking.listeners[3]: invalid listener, IPv6 addresses must be in brackets, \
+                 found \"::1:8080\"",
+                "networking.listeners[4]: invalid listener, path must not be empty",
+                "networking.listeners[5]: invalid listener, directory \"/missing\" does not exist",
+                "networking.listeners[6]: invalid listener, name must not be empty",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_listeners() {
+        let yaml = listeners(&[
+            "{type: tcp, address: 127.0.0.1:8080}",
+            "{type: unix, path: /tmp/fn.sock}",
+            "{type: tcp, address: 127.0.0.1:8080}",
+            "{type: unix, path: /tmp/fn.sock}",
+        ]);
+
+        assert_eq!(
+            problems(&yaml),
+            vec![
+                "networking.listeners[2]: invalid listener, already used by \
+                 networking.listeners[0]",
+                "networking.listeners[3]: invalid listener, already used by \
+                 networking.listeners[1]",
+            ]
+        );
+    }
+
+    #[test]
+    fn unnamed_systemd_listener() {
+        let yaml = listeners(&["{type: systemd}", "{type: systemd, name: web}"]);
+
+        assert_eq!(
+            problems(&yaml),
+            vec![
+                "networking.listeners: invalid listener, a systemd listener without a name uses \
+                 every socket, so it can't be combined with other systemd listeners"
+            ]
+        );
+    }
+
+    #[test]
+    fn internal_listeners() {
+        let yaml = listeners(&[
+            "{type: tcp, address: 127.0.0.1:8080}",
+            "{type: tcp, address: 127.0.0.1:9090, internal: true}",
+        ]);
+
+        assert_eq!(
+            problems(&yaml),
+            vec![
+                "networking.listeners[1]: invalid listener, internal listeners require \
+                 gateway.runtime_store to be shared"
+            ]
+        );
+
+        let only_internal = format!(
+            "{}gateway:\n  runtime_store: shared\n",
+            listeners(&["{type: tcp, address: 127.0.0.1:9090, internal: true}"])
+        );
+
+        assert_eq!(
+            problems(&only_internal),
+            vec!["networking.listeners: at least 1 listener must not be internal"]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use fn_core::config::{Config, FunctionConfig};
+use fn_core::runtime::{evict, restart};
+use fn_core::state::AppData;
+use fn_core::tracing::Span;
+use log::error;
+use ring::constant_time::verify_slices_are_equal;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::handlers::find_runtime;
+
+/// Registered with the app for the admin API
+pub(crate) struct Admin {
+    token: String,
+    /// Functions as configured when the pod started
+    functions: Vec<FunctionConfig>,
+    /// Re-read when the config is reloaded
+    config_path: PathBuf,
+}
+
+impl Admin {
+    pub fn new(token: String, config: &Config) -> Admin {
+        Admin {
+            token,
+            functions: config.functions().clone(),
+            config_path: config.path().to_path_buf(),
+        }
+    }
+
+    /// The function's current config, which may have been reloaded since the pod started
+    fn function(&self, state: &AppData, name: &str) -> Option<FunctionConfig> {
+        state.reloaded_config(name).or_else(|| {
+            self.functions
+                .iter()
+                .find(|func| func.name() == name)
+                .cloned()
+        })
+    }
+}
+
+type AdminData = web::Data<Admin>;
+
+/// Register the routes of the admin API under the prefix
+pub(crate) fn service(prefix: &str) -> actix_web::Scope {
+    web::scope(prefix.trim_end_matches('/'))
+        .route("/functions", web::get().to(list))
+        .route("/functions/{name}", web::get().to(show))
+        .route(
+            "/functions/{name}/restart",
+            web::post().to(restart_function),
+        )
+        .route("/functions/{name}/evict", web::post().to(evict_function))
+        .route("/functions/{name}/warm", web::post().to(warm))
+        .route("/functions/{name}/drain", web::post().to(drain))
+        .route("/functions/{name}/drain", web::delete().to(undrain))
+        .route("/config/reload", web::post().to(reload))
+}
+
+/// Every request must have the token as a bearer token
+fn authorize(admin: &Admin, req: &HttpRequest) -> Result<(), HttpResponse> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.starts_with("Bearer "))
+        .map(|value| value["Bearer ".len()..].trim());
+
+    // compared in constant time, so the token can't be guessed from how long the check takes
+    let authorized = match token {
+        Some(token) => verify_slices_are_equal(token.as_bytes(), admin.token.as_bytes()).is_ok(),
+        None => false,
+    };
+
+    if authorized {
+        Ok(())
+    } else {
+        Err(HttpResponse::Unauthorized()
+            .header(header::WWW_AUTHENTICATE, "Bearer")
+            .json(json!({ "error": "A valid admin token is required" })))
+    }
+}
+
+fn not_found(name: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": format!("Function {} does not exist", name) }))
+}
+
+/// The function's config and the state of its runtime
+fn describe(state: &AppData, func: &FunctionConfig) -> serde_json::Value {
+    let info = state.info(func.name());
+
+    let runtime = state.handles.read().get(func.name()).cloned();
+
+    let instance = runtime.map(|runtime| {
+        let lock_guard = runtime.read();
+        let usage = lock_guard.resource_usage().unwrap_or_default();
+
+        json!({
+            "pid": lock_guard.pid(),
+            "uptime_secs": info.started.map(|started| started.elapsed().as_secs()),
+            "memory_bytes": usage.memory_bytes,
+            "cpu_usec": usage.cpu_usec,
+            "pids": usage.pids,
+        })
+    });
+
+    json!({
+        "name": func.name(),
+        "route": func.route,
+        "methods": func.method.list(),
+        "runtime": func.runtime,
+        "status": info.status,
+        "draining": info.draining,
+        "requests": info.requests,
+        "last_error": info.last_error,
+        "instance": instance,
+    })
+}
+
+fn list(state: AppData, admin: AdminData, req: HttpRequest) -> HttpResponse {
+    if let Err(res) = authorize(&admin, &req) {
+        return res;
+    }
+
+    let functions: Vec<serde_json::Value> = admin
+        .functions
+        .iter()
+        .filter_map(|func| admin.function(&state, func.name()))
+        .map(|func| describe(&state, &func))
+        .collect();
+
+    HttpResponse::Ok().json(json!({ "functions": functions }))
+}
+
+fn show(
+    state: AppData,
+    admin: AdminData,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> HttpResponse {
+    if let Err(res) = authorize(&admin, &req) {
+        return res;
+    }
+
+    match admin.function(&state, &name) {
+        Some(func) => HttpResponse::Ok().json(describe(&state, &func)),
+        None => not_found(&name),
+    }
+}
+
+/// Restart the function's process, whether or not it has crashed
+fn restart_function(
+    state: AppData,
+    admin: AdminData,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> HttpResponse {
+    if let Err(res) = authorize(&admin, &req) {
+        return res;
+    }
+
+    let func = match admin.function(&state, &name) {
+        Some(func) => func,
+        None => return not_found(&name),
+    };
+
+    let runtime = match state.handles.read().get(func.name()) {
+        Some(runtime) => runtime.clone(),
+        None => {
+            return HttpResponse::Conflict()
+                .json(json!({ "error": format!("Function {} is not running", func.name()) }));
+        }
+    };
+
+    let restarted = {
+        let mut lock_guard = runtime.write();
+        restart(&state, &func, &mut *lock_guard)
+    };
+
+    match restarted {
+        Ok(()) => HttpResponse::Ok().json(describe(&state, &func)),
+        Err(e) => server_error(&e),
+    }
+}
+
+/// Shut down the function and drop it from the cache, its next request starts it cold
+fn evict_function(
+    state: AppData,
+    admin: AdminData,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> HttpResponse {
+    if let Err(res) = authorize(&admin, &req) {
+        return res;
+    }
+
+    let func = match admin.function(&state, &name) {
+        Some(func) => func,
+        None => return not_found(&name),
+    };
+
+    match evict(&state, &func) {
+        Ok(evicted) => HttpResponse::Ok().json(json!({ "evicted": evicted })),
+        Err(e) => server_error(&e),
+    }
+}
+
+/// Initialize the function now, rather than on its next request
+fn warm(
+    state: AppData,
+    admin: AdminData,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> HttpResponse {
+    if let Err(res) = authorize(&admin, &req) {
+        return res;
+    }
+
+    let func = match admin.function(&state, &name) {
+        Some(func) => func,
+        None => return not_found(&name),
+    };
+
+    let span = Span::start("warm up", None);
+
+    match find_runtime(state.clone(), &func, span.context()) {
+        Ok(_) => HttpResponse::Ok().json(describe(&state, &func)),
+        Err(e) => server_error(&e),
+    }
+}
+
+/// Stop sending new requests to the function, they're answered with a 503 until it's undrained.
+/// Requests that are already in flight are left to finish
+fn drain(
+    state: AppData,
+    admin: AdminData,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> HttpResponse {
+    set_draining(state, admin, req, name, true)
+}
+
+fn undrain(
+    state: AppData,
+    admin: AdminData,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> HttpResponse {
+    set_draining(state, admin, req, name, false)
+}
+
+fn set_draining(
+    state: AppData,
+    admin: AdminData,
+    req: HttpRequest,
+    name: web::Path<String>,
+    draining: bool,
+) -> HttpResponse {
+    if let Err(res) = authorize(&admin, &req) {
+        return res;
+    }
+
+    let func = match admin.function(&state, &name) {
+        Some(func) => func,
+        None => return not_found(&name),
+    };
+
+    state.set_draining(func.name(), draining);
+
+    HttpResponse::Ok().json(describe(&state, &func))
+}
+
+/// Re-read the config file. Functions whose routing is unchanged take the new config, their
+/// runtimes are evicted so that the next request starts them with it. Adding or removing functions
+/// and changing routes, methods or route types needs a restart of the pod, those are reported
+/// rather than applied
+fn reload(state: AppData, admin: AdminData, req: HttpRequest) -> HttpResponse {
+    if let Err(res) = authorize(&admin, &req) {
+        return res;
+    }
+
+    let config = match Config::load_from(&admin.config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            return HttpResponse::UnprocessableEntity().json(json!({ "error": e.to_string() }));
+        }
+    };
+
+    if let Err(errors) = config.validate() {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        return HttpResponse::UnprocessableEntity().json(json!({ "errors": errors }));
+    }
+
+    let mut reloaded = vec![];
+    let mut unchanged = vec![];
+    let mut requires_restart = vec![];
+
+    for func in config.functions_iter() {
+        let current = match admin.function(&state, func.name()) {
+            Some(current) => current,
+            None => {
+                requires_restart.push(func.name().to_string());
+                continue;
+            }
+        };
+
+        if current.route != func.route
+            || current.method.list() != func.method.list()
+            || current.route_type != func.route_type
+        {
+            requires_restart.push(func.name().to_string());
+        } else if settings(&current) == settings(func) {
+            unchanged.push(func.name().to_string());
+        } else {
+            state.set_reloaded_config(func.clone());
+
+            if let Err(e) = evict(&state, &current) {
+                error!("Failed to shut down function {}: {}", func.name(), e);
+            }
+
+            reloaded.push(func.name().to_string());
+        }
+    }
+
+    for func in admin.functions.iter() {
+        if config.functions_iter().all(|new| new.name() != func.name()) {
+            requires_restart.push(func.name().to_string());
+        }
+    }
+
+    HttpResponse::Ok().json(json!({
+        "reloaded": reloaded,
+        "unchanged": unchanged,
+        "requires_restart": requires_restart,
+    }))
+}
+
+/// The config as it was written, without the id that's generated on every load
+fn settings(func: &FunctionConfig) -> serde_json::Value {
+    let mut value = serde_json::to_value(func).unwrap_or_default();
+
+    if let Some(object) = value.as_object_mut() {
+        object.remove("id");
+    }
+
+    value
+}
+
+fn server_error(error: &failure::Error) -> HttpResponse {
+    HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
+        .json(json!({ "error": error.to_string() }))
+}
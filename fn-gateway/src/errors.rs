@@ -0,0 +1,87 @@
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use fn_core::runtime::ErrorKind;
+
+use crate::handlers::REQUEST_ID_HEADER;
+
+/// Status of the response for each kind of error
+pub(crate) fn status(kind: ErrorKind) -> StatusCode {
+    match kind {
+        ErrorKind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorKind::BadGateway | ErrorKind::MalformedResponse => StatusCode::BAD_GATEWAY,
+        ErrorKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        ErrorKind::Crashed | ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Description of the error that is safe to show to clients
+fn detail(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Unavailable => "The function could not be started",
+        ErrorKind::BadGateway => "The function could not be reached",
+        ErrorKind::Timeout => "The function did not respond in time",
+        ErrorKind::Crashed => "The function failed while handling the request",
+        ErrorKind::MalformedResponse => "The function returned an invalid response",
+        ErrorKind::Internal => "An internal error occurred",
+    }
+}
+
+/// Build an RFC 7807 (application/problem+json) response for the error. The error's message and
+/// causes can leak internal details, so they are only included when `expose` is set.
+pub(crate) fn problem(
+    kind: ErrorKind,
+    function: &str,
+    request_id: &str,
+    error: &failure::Error,
+    expose: bool,
+) -> HttpResponse {
+    let status = status(kind);
+    let mut body = problem_body(status, detail(kind), function, request_id);
+
+    if expose {
+        body["errors"] = serde_json::json!(error
+            .iter_chain()
+            .map(|cause| cause.to_string())
+            .collect::<Vec<String>>());
+    }
+
+    respond(status, request_id, body)
+}
+
+/// The request's body is larger than the function accepts
+pub(crate) fn payload_too_large(function: &str, request_id: &str, limit: usize) -> HttpResponse {
+    let status = StatusCode::PAYLOAD_TOO_LARGE;
+    let detail = format!(
+        "The request body is larger than the limit of {} bytes",
+        limit
+    );
+
+    respond(
+        status,
+        request_id,
+        problem_body(status, &detail, function, request_id),
+    )
+}
+
+fn problem_body(
+    status: StatusCode,
+    detail: &str,
+    function: &str,
+    request_id: &str,
+) -> serde_json::Value {
+    serde_json::json!({
+        "type": "about:blank",
+        "title": status.canonical_reason().unwrap_or("Error"),
+        "status": status.as_u16(),
+        "detail": detail,
+        "function": function,
+        "request_id": request_id,
+    })
+}
+
+fn respond(status: StatusCode, request_id: &str, body: serde_json::Value) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("application/problem+json")
+        .header(REQUEST_ID_HEADER, request_id)
+        .body(body.to_string())
+}
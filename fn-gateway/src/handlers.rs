@@ -1,131 +1,909 @@
+use actix_web::dev::{HttpResponseBuilder, Path, ResourceDef};
+use actix_web::error::PayloadError;
 use actix_web::web::Payload;
 use actix_web::{Error, HttpRequest, HttpResponse};
+use bytes::{Bytes, BytesMut};
+use futures::future::{ok, result};
 use futures::{Future, Stream};
 
-use actix_web::http::StatusCode;
+use actix_web::http::{header, Method, StatusCode};
 
 use fn_api::{ConvertFunction, FunctionContext, FunctionRequest, FunctionResponse};
-use fn_core::config::FunctionConfig;
-use fn_core::runtime::RuntimeManager;
+use fn_core::config::{FunctionConfig, RouteType};
+use fn_core::metrics;
+use fn_core::query;
+use fn_core::runtime::{
+    duration_seconds, ErrorKind, MessageHandler, RuntimeError, RuntimeManager, StreamingRequest,
+    StreamingResponse, WebSocketConnection,
+};
 use fn_core::state::AppData;
+use fn_core::tracing::{Span, SpanContext, TRACEPARENT_HEADER};
 use fn_unix_socket_runtime::runtime::UnixSocketRuntime;
 use fn_wasm_runtime::runtime::WasmRuntime;
 
-use actix_web::dev::Body;
-use bytes::Bytes;
+use crate::errors;
+use crate::routes::Route;
+use crate::streaming;
+use crate::websocket;
+use crate::Errors;
+use log::error;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
 
-/// Determine the runtime to use from the FunctionConfig and send the request to that runtime.
-/// If the runtime has not been initialized, this will result in a cold start for the function.
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id accepted from the client
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Find the runtime of the function from the FunctionConfig, if the runtime has not been
+/// initialized this will result in a cold start for the function.
+pub(crate) fn find_runtime(
+    data: AppData,
+    config: &FunctionConfig,
+    parent: SpanContext,
+) -> Result<Arc<RwLock<dyn RuntimeManager>>, failure::Error> {
+    let mut span = Span::start("runtime lookup", Some(parent));
+    span.set_attribute("function", config.name());
+
+    let runtime = match config.runtime.as_str() {
+        "unix_socket" => UnixSocketRuntime::find_or_initialize(data, config, span.context()),
+        "wasm" => WasmRuntime::find_or_initialize(data, config, span.context()),
+        _ => Err(Errors::UnknownRuntime(config.runtime.clone()).into()),
+    };
+
+    if let Err(e) = &runtime {
+        span.set_error(e);
+    }
+
+    runtime
+}
+
+/// Start the span of the call to the function, its trace context is passed to the function so that
+/// it can continue the trace
+fn invoke_span(
+    config: &FunctionConfig,
+    parent: SpanContext,
+    payload: &mut FunctionContext,
+) -> Span {
+    let mut span = Span::start("invoke", Some(parent));
+    span.set_attribute("function", config.name());
+    span.set_attribute("runtime", &config.runtime);
+
+    payload.traceparent = Some(span.context().traceparent());
+
+    span
+}
+
+/// Count the call to the function, and mark the span as failed when the function failed
+fn record_result<T>(
+    data: &AppData,
+    config: &FunctionConfig,
+    mut span: Span,
+    res: Result<T, failure::Error>,
+) -> Result<T, failure::Error> {
+    data.record_request(config.name(), res.as_ref().err());
+
+    if let Err(e) = &res {
+        span.set_error(e);
+    }
+
+    res
+}
+
+/// Send the request to the runtime of the function
 fn handle_request(
     data: AppData,
-    config: FunctionConfig,
-    payload: FunctionContext,
+    config: &FunctionConfig,
+    parent: SpanContext,
+    mut payload: FunctionContext,
 ) -> Result<Vec<u8>, failure::Error> {
-    let runtime = match config.runtime.as_str() {
-        "unix_socket" => UnixSocketRuntime::find_or_initialize(data, &config)?,
-        "wasm" => WasmRuntime::find_or_initialize(data, &config)?,
-        _ => {
-            unimplemented!("Runtime ({}) does not exist", config.runtime);
+    let runtime = find_runtime(data.clone(), config, parent)?;
+    let span = invoke_span(config, parent, &mut payload);
+
+    let lock_guard = runtime.read();
+    let res = lock_guard.handle_request(payload);
+
+    record_result(&data, config, span, res)
+}
+
+/// Send the request to the runtime of the function, which streams back its response
+fn handle_streaming(
+    data: AppData,
+    config: &FunctionConfig,
+    parent: SpanContext,
+    mut payload: FunctionContext,
+) -> Result<StreamingResponse, failure::Error> {
+    let runtime = find_runtime(data.clone(), config, parent)?;
+    let span = invoke_span(config, parent, &mut payload);
+
+    let lock_guard = runtime.read();
+    let res = lock_guard.handle_streaming(payload);
+
+    record_result(&data, config, span, res)
+}
+
+/// Open a connection to the runtime of the function for a websocket
+pub(crate) fn connect_websocket(
+    data: AppData,
+    config: &FunctionConfig,
+    parent: SpanContext,
+    on_message: MessageHandler,
+) -> Result<Box<dyn WebSocketConnection>, failure::Error> {
+    let runtime = find_runtime(data, config, parent)?;
+
+    let lock_guard = runtime.read();
+    lock_guard.connect_websocket(on_message)
+}
+
+/// Start a request to the runtime of the function, the body is written to the returned request
+fn stream_request(
+    data: AppData,
+    config: &FunctionConfig,
+    parent: SpanContext,
+    mut payload: FunctionContext,
+) -> Result<Box<dyn StreamingRequest>, failure::Error> {
+    let runtime = find_runtime(data.clone(), config, parent)?;
+    let span = invoke_span(config, parent, &mut payload);
+
+    let lock_guard = runtime.read();
+    let res = lock_guard.stream_request(payload);
+
+    record_result(&data, config, span, res)
+}
+
+/// Send a single request to the function without going through the HTTP server, used to run a
+/// function from the command line. The path has to match the route, which its parameters are taken
+/// from, and a streamed response is collected into the body
+pub fn invoke(
+    state: AppData,
+    config: &FunctionConfig,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> Result<FunctionResponse, failure::Error> {
+    if config.route_type == RouteType::WebSocket {
+        Err(Errors::NotInvocable(config.name().to_string()))?;
+    }
+
+    let (path, query_string) = match path.find('?') {
+        Some(index) => (&path[..index], &path[index + 1..]),
+        None => (path, ""),
+    };
+    let request_id = Uuid::new_v4().to_string();
+
+    // the parameters are taken from the path the same way that the router does
+    let mut params = Path::new(path);
+    if !ResourceDef::new(config.route_pattern().as_str()).match_path(&mut params) {
+        Err(Errors::UnmatchedPath(
+            path.to_string(),
+            config.route.clone(),
+        ))?;
+    }
+
+    let mut func_req =
+        FunctionRequest::new(config.name(), &config.handler, path, method, query_string);
+    func_req.query = query::parse(query_string);
+    func_req.route = &config.route;
+    func_req.params = params
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    func_req.request_id = &request_id;
+
+    if !config.stream_body {
+        func_req.body = body;
+    }
+
+    let payload = FunctionContext::new(func_req, FunctionResponse::new());
+    let span = Span::server(&format!("{} {}", method, config.route), None);
+
+    let func_res = match (config.stream_body, config.stream_response) {
+        (false, false) => return decode(&handle_request(state, config, span.context(), payload)?),
+        (false, true) => handle_streaming(state, config, span.context(), payload)?,
+        (true, stream_response) => {
+            let mut stream = stream_request(state, config, span.context(), payload)?;
+            stream.write_chunk(body)?;
+
+            if !stream_response {
+                return decode(&stream.finish()?);
+            }
+
+            stream.finish_streaming()?
         }
     };
 
-    let lock_guard = runtime.read();
-    lock_guard.handle_request(payload)
+    func_res.collect()
 }
 
-/// Handles an incoming response and forwards it to the function.
-///
-/// When the function responds, we first check for errors in stderr (error occurring during function runtime)
-/// and the error prop (error that occurs during function invocation).
-///
-/// Next, if there are no errors we try to convert stdout's bytes to a string, if this fails an
-/// internal server error is sent, ending the request.
-///
-/// Next, if we were able to convert to a string, we try to convert the string into a Function Response.
-///
-/// If this is done we use that data (headers, body, etc..) to send the request.
-///
-/// If is not successful we just send the string w/o setting any special headers.
-///
-pub(crate) fn web_handler(state: AppData, req: HttpRequest, payload: Option<&str>) -> HttpResponse {
-    // get the config from the request
-    let config: Option<&FunctionConfig> = req.app_data();
+fn decode(func_res: &[u8]) -> Result<FunctionResponse, failure::Error> {
+    Ok(FunctionResponse::from_slice(func_res)?)
+}
+
+/// Values taken from the HttpRequest that the FunctionRequest borrows
+pub(crate) struct RequestInfo {
+    pub request_id: String,
+    remote_addr: Option<String>,
+    version: String,
+    scheme: String,
+    host: String,
+}
 
-    if config.is_none() {
-        return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Route configuration missing script function"
-        }));
+impl RequestInfo {
+    pub fn new(req: &HttpRequest) -> RequestInfo {
+        let (scheme, host) = {
+            let info = req.connection_info();
+            (info.scheme().to_string(), info.host().to_string())
+        };
+
+        RequestInfo {
+            request_id: request_id(req),
+            remote_addr: req.peer_addr().map(|addr| addr.to_string()),
+            version: format!("{:?}", req.version()),
+            scheme,
+            host,
+        }
     }
 
-    let config = config.unwrap();
+    /// Set the values of the request that are borrowed from the info
+    pub fn describe<'a>(&'a self, func_req: &mut FunctionRequest<'a>) {
+        func_req.request_id = &self.request_id;
+        func_req.remote_addr = self.remote_addr.as_ref().map(|addr| addr.as_str());
+        func_req.version = &self.version;
+        func_req.scheme = &self.scheme;
+        func_req.host = &self.host;
+    }
+}
 
-    // convert the HttpRequest to the FunctionRequest
+/// Convert the HttpRequest to the FunctionContext passed to the function
+pub(crate) fn function_context<'a>(
+    req: &'a HttpRequest,
+    info: &'a RequestInfo,
+    config: &'a FunctionConfig,
+    method: &'a str,
+    payload: &'a [u8],
+) -> FunctionContext<'a> {
     let mut func_req = FunctionRequest::new(
+        config.name(),
         &config.handler,
         req.path(),
-        req.method().as_str(),
+        method,
         req.query_string(),
     );
     let func_res = FunctionResponse::new();
 
-    if payload.is_some() {
-        func_req.body = payload;
+    func_req.body = payload;
+
+    for (name, value) in req.headers().iter() {
+        if !config.forward_headers.allows(name.as_str()) {
+            continue;
+        }
+
+        if let Ok(value) = value.to_str() {
+            func_req.headers.append(name.as_str(), value);
+        }
+    }
+
+    // the cookies are only passed when the header they're parsed from is forwarded
+    if config.forward_headers.allows(header::COOKIE.as_str()) {
+        if let Ok(cookies) = req.cookies() {
+            for cookie in cookies.iter() {
+                func_req
+                    .cookies
+                    .entry(cookie.name().to_string())
+                    .or_insert_with(|| cookie.value().to_string());
+            }
+        }
     }
 
-    // attempt to serialize the FunctionRequest to pass to function handler
-    let func_payload = FunctionContext::new(func_req, func_res);
+    func_req.query = query::parse(req.query_string());
+    func_req.route = &config.route;
+    func_req.params = req
+        .match_info()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    info.describe(&mut func_req);
+
+    FunctionContext::new(func_req, func_res)
+}
+
+/// Sends the request, with its whole body, to the function and responds with its result.
+fn web_handler(state: AppData, call: &Invocation, payload: &[u8]) -> HttpResponse {
+    let func_payload = function_context(&call.req, &call.info, &call.func, &call.method, payload);
+
+    if call.func.stream_response {
+        let func_res = handle_streaming(state, &call.func, call.trace, func_payload);
+
+        return call.respond_streaming(func_res);
+    }
 
     // the runtime manager is responsible for any serialization
-    let func_res = handle_request(state, config.clone(), func_payload);
+    let func_res = handle_request(state, &call.func, call.trace, func_payload);
+
+    call.respond(func_res)
+}
 
+/// Handles the result of the function.
+///
+/// When the function responds, we try to convert the bytes into a Function Response and use that
+/// data (headers, body, etc..) to send the response.
+///
+/// If the function fails, or its response can't be converted, the error is classified and sent as
+/// an application/problem+json response with a status that reflects what went wrong.
+///
+fn function_response(
+    expose_errors: bool,
+    config: &FunctionConfig,
+    request_id: &str,
+    func_res: Result<Vec<u8>, failure::Error>,
+) -> HttpResponse {
     match func_res {
         Ok(func_res) => {
-            let res = FunctionResponse::from_slice(&func_res);
+            let func_res = match FunctionResponse::from_slice(&func_res) {
+                Ok(func_res) => func_res,
+                Err(e) => {
+                    let error: failure::Error =
+                        RuntimeError::MalformedResponse(config.name().to_string(), e.to_string())
+                            .into();
 
-            if res.is_err() {
-                println!("is error {:?}", res);
-                let mut res = HttpResponse::build(StatusCode::OK);
-                res.set_header("content-type", "text/plain");
-                return res.body(Body::Bytes(Bytes::from(func_res)));
-            }
+                    return error_response(expose_errors, config, request_id, error);
+                }
+            };
+
+            response_builder(request_id, &func_res).body(func_res.body)
+        }
+        Err(e) => error_response(expose_errors, config, request_id, e),
+    }
+}
+
+/// Handles the result of a function that streams its response, the head is sent as soon as it
+/// arrives and each chunk of the body is sent as the function sends it.
+///
+/// Server-sent events are marked as uncacheable and unbuffered, so that each event reaches the
+/// client right away.
+fn streaming_response(
+    expose_errors: bool,
+    config: &FunctionConfig,
+    request_id: &str,
+    func_res: Result<StreamingResponse, failure::Error>,
+) -> HttpResponse {
+    let func_res = match func_res {
+        Ok(func_res) => func_res,
+        Err(e) => return error_response(expose_errors, config, request_id, e),
+    };
 
-            let func_res = res.unwrap();
+    let head = match FunctionResponse::from_slice(&func_res.head) {
+        Ok(head) => head,
+        Err(e) => {
+            let error: failure::Error =
+                RuntimeError::MalformedResponse(config.name().to_string(), e.to_string()).into();
 
-            let status_code = match StatusCode::from_u16(func_res.status_code) {
-                Ok(status_code) => status_code,
-                _ => StatusCode::OK,
-            };
+            return error_response(expose_errors, config, request_id, error);
+        }
+    };
 
-            let mut http_res = HttpResponse::build(status_code);
+    let mut http_res = response_builder(request_id, &head);
+
+    if streaming::is_event_stream(&head) {
+        if !head.headers.contains("cache-control") {
+            http_res.header(header::CACHE_CONTROL, "no-cache");
+        }
+
+        // keeps proxies such as nginx from buffering the events
+        http_res.header("x-accel-buffering", "no");
+    }
+
+    http_res.streaming(streaming::body_stream(
+        config.name().to_string(),
+        request_id.to_string(),
+        func_res.body,
+    ))
+}
+
+/// Start the response with the status, headers and cookies set by the function
+fn response_builder(request_id: &str, func_res: &FunctionResponse) -> HttpResponseBuilder {
+    let status_code = match StatusCode::from_u16(func_res.status_code) {
+        Ok(status_code) => status_code,
+        _ => StatusCode::OK,
+    };
+
+    let mut http_res = HttpResponse::build(status_code);
+
+    http_res.header(REQUEST_ID_HEADER, request_id);
+
+    // header appends, so every value of a header is sent
+    func_res.headers.iter().for_each(|(k, v)| {
+        http_res.header(k, v);
+    });
+
+    func_res.cookies.iter().for_each(|cookie| {
+        http_res.header(header::SET_COOKIE, cookie.to_string());
+    });
+
+    http_res
+}
+
+/// Log the error with the id of the request, and respond with the problem
+fn error_response(
+    expose_errors: bool,
+    config: &FunctionConfig,
+    request_id: &str,
+    error: failure::Error,
+) -> HttpResponse {
+    let kind = ErrorKind::of(&error);
+
+    if kind == ErrorKind::Timeout {
+        metrics::TIMEOUTS
+            .with_label_values(&[config.name(), &config.route, &config.runtime])
+            .inc();
+    }
+
+    error!(
+        "[{}] {:?} error in function {}: {}",
+        request_id,
+        kind,
+        config.name(),
+        error
+    );
+
+    errors::problem(kind, config.name(), request_id, &error, expose_errors)
+}
+
+/// Why the body of a request couldn't be read
+enum BodyError {
+    /// The body is larger than the function accepts
+    TooLarge,
+    /// The function failed while the body was being streamed to it
+    Function(failure::Error),
+    Payload(PayloadError),
+}
 
-            if func_res.headers.len() > 0 {
-                func_res.headers.iter().for_each(|(k, v)| {
-                    http_res.header(k.as_str(), v.as_str());
-                });
+impl From<PayloadError> for BodyError {
+    fn from(error: PayloadError) -> BodyError {
+        BodyError::Payload(error)
+    }
+}
+
+type HandlerFuture = Box<dyn Future<Item = HttpResponse, Error = Error>>;
+
+/// Handles every request to a route. The body is read for any method, up to the function's limit,
+/// then the request is sent to the function that handles the method.
+///
+/// HEAD falls back to the GET function, and OPTIONS is answered with the allowed methods, unless a
+/// function handles them itself. Any other method without a function gets a 405.
+pub(crate) fn handler(payload: Payload, state: AppData, req: HttpRequest) -> HandlerFuture {
+    let route: Option<&Route> = req.app_data();
+
+    let route = match route {
+        Some(route) => route,
+        None => {
+            return Box::new(ok(HttpResponse::InternalServerError().json(
+                serde_json::json!({
+                    "error": "Route configuration missing functions"
+                }),
+            )));
+        }
+    };
+
+    let method = req.method().as_str();
+
+    // continues the trace of the caller, when it sent one
+    let parent = req
+        .headers()
+        .get(TRACEPARENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(SpanContext::parse);
+
+    let mut span = Span::server(&format!("{} {}", method, route.route), parent);
+    span.set_attribute("http.method", method);
+    span.set_attribute("http.route", &route.route);
+
+    let route_span = Span::start("route", Some(span.context()));
+
+    // the function sees a GET for a HEAD without its own function, so that it responds exactly as
+    // it would to one, only the body is left out of the response
+    let matched = match route.find(method) {
+        Some(func) => Ok((func.clone(), method.to_string(), false)),
+        None => {
+            let allow = route.allowed_methods().join(", ");
+
+            match (req.method(), route.find("GET")) {
+                (&Method::HEAD, Some(func)) => Ok((func.clone(), "GET".to_string(), true)),
+                (&Method::OPTIONS, _) => Err(HttpResponse::NoContent()
+                    .header(header::ALLOW, allow)
+                    .finish()),
+                _ => Err(method_not_allowed(&allow)),
             }
+        }
+    };
+
+    drop(route_span);
+
+    let (func, method, head) = match matched {
+        // a config reloaded through the admin API replaces the one the route was registered with
+        Ok((func, method, head)) => match state.reloaded_config(func.name()) {
+            Some(reloaded) => (reloaded, method, head),
+            None => (func, method, head),
+        },
+        Err(res) => {
+            span.set_attribute("http.status_code", res.status().as_u16());
+            return Box::new(ok(res));
+        }
+    };
+
+    let metrics = RequestMetrics::start(&func);
+
+    let call = Invocation {
+        info: RequestInfo::new(&req),
+        limit: func.max_body_bytes.unwrap_or(route.max_body_bytes),
+        expose_errors: route.expose_errors,
+        trace: span.context(),
+        req,
+        func,
+        method,
+    };
+
+    span.set_attribute("function", call.func.name());
+    span.set_attribute("request_id", &call.info.request_id);
+
+    let res = call_function(payload, state, call);
+
+    let res = if head {
+        Box::new(res.map(without_body))
+    } else {
+        res
+    };
+
+    Box::new(res.then(move |res| {
+        let status = response_status(&res);
+
+        metrics.finish(status);
+
+        span.set_attribute("http.status_code", status.as_u16());
+        if status.is_server_error() {
+            span.set_error(status);
+        }
 
-            http_res.body(func_res.body)
+        res
+    }))
+}
+
+/// Sends the request to the function, as a websocket or with its body buffered or streamed
+fn call_function(payload: Payload, state: AppData, call: Invocation) -> HandlerFuture {
+    if state.is_draining(call.func.name()) {
+        let error = RuntimeError::Draining(call.func.name().to_string()).into();
+
+        return Box::new(ok(call.respond(Err(error))));
+    }
+
+    if call.func.route_type == RouteType::WebSocket {
+        return Box::new(result(websocket::start(
+            state, &call.req, call.func, call.trace, payload,
+        )));
+    }
+
+    // reject the request before reading the body when it declares that it's too large
+    if content_length(&call.req).map_or(false, |len| len > call.limit) {
+        return Box::new(ok(call.too_large()));
+    }
+
+    if call.func.stream_body {
+        streamed(payload, state, call)
+    } else {
+        buffered(payload, state, call)
+    }
+}
+
+/// Records the metrics of a request to a function
+struct RequestMetrics {
+    function: String,
+    route: String,
+    started: Instant,
+}
+
+impl RequestMetrics {
+    fn start(func: &FunctionConfig) -> RequestMetrics {
+        metrics::QUEUE_DEPTH
+            .with_label_values(&[func.name(), &func.route])
+            .inc();
+
+        RequestMetrics {
+            function: func.name().to_string(),
+            route: func.route.clone(),
+            started: Instant::now(),
         }
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+
+    /// Record the duration and status once the response is ready
+    fn finish(&self, status: StatusCode) {
+        metrics::REQUEST_DURATION
+            .with_label_values(&[&self.function, &self.route])
+            .observe(duration_seconds(self.started.elapsed()));
+        metrics::REQUESTS
+            .with_label_values(&[&self.function, &self.route, status.as_str()])
+            .inc();
     }
 }
 
-/// Handles POST request to the gateway
-pub(crate) fn post_handler(
-    payload: Payload,
-    state: AppData,
-    req: HttpRequest,
-) -> impl Future<Item = HttpResponse, Error = Error> {
-    payload.concat2().from_err().and_then(|b: bytes::Bytes| {
-        let body: Option<&str> = std::str::from_utf8(b.as_ref()).ok();
-        web_handler(state, req, body)
-    })
+impl Drop for RequestMetrics {
+    fn drop(&mut self) {
+        // also reached when the client goes away before the response is ready
+        metrics::QUEUE_DEPTH
+            .with_label_values(&[&self.function, &self.route])
+            .dec();
+    }
 }
 
-/// Handles GET request to the gateway
-pub(crate) fn get_handler(
-    state: AppData,
+/// Status of the response, or of the response that the error becomes
+fn response_status(res: &Result<HttpResponse, Error>) -> StatusCode {
+    match res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().error_response().status(),
+    }
+}
+
+/// Everything needed to call the function once it has been matched to the request
+struct Invocation {
     req: HttpRequest,
-) -> impl Future<Item = HttpResponse, Error = Error> {
-    futures::future::ok(web_handler(state, req, None))
+    info: RequestInfo,
+    func: FunctionConfig,
+    method: String,
+    expose_errors: bool,
+    /// Largest body accepted by the function
+    limit: usize,
+    /// Context of the request's span, the parent of the spans of the call
+    trace: SpanContext,
+}
+
+impl Invocation {
+    fn respond(&self, func_res: Result<Vec<u8>, failure::Error>) -> HttpResponse {
+        function_response(
+            self.expose_errors,
+            &self.func,
+            &self.info.request_id,
+            func_res,
+        )
+    }
+
+    fn respond_streaming(
+        &self,
+        func_res: Result<StreamingResponse, failure::Error>,
+    ) -> HttpResponse {
+        streaming_response(
+            self.expose_errors,
+            &self.func,
+            &self.info.request_id,
+            func_res,
+        )
+    }
+
+    fn too_large(&self) -> HttpResponse {
+        errors::payload_too_large(self.func.name(), &self.info.request_id, self.limit)
+    }
+
+    fn body_error(&self, error: BodyError) -> Result<HttpResponse, Error> {
+        match error {
+            BodyError::TooLarge => Ok(self.too_large()),
+            BodyError::Function(e) => Ok(self.respond(Err(e))),
+            BodyError::Payload(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Reads the whole body, then sends the request to the function
+fn buffered(payload: Payload, state: AppData, call: Invocation) -> HandlerFuture {
+    let limit = call.limit;
+
+    let body = payload.fold(BytesMut::new(), move |mut body, chunk: Bytes| {
+        if body.len() + chunk.len() > limit {
+            return Err(BodyError::TooLarge);
+        }
+
+        body.extend_from_slice(&chunk);
+        Ok(body)
+    });
+
+    Box::new(body.then(move |body| match body {
+        // passed to the function untouched, the runtime decides how to encode it
+        Ok(body) => Ok(web_handler(state, &call, &body)),
+        Err(e) => call.body_error(e),
+    }))
+}
+
+/// Starts the request with the function, then writes each chunk of the body to it as it arrives
+fn streamed(payload: Payload, state: AppData, call: Invocation) -> HandlerFuture {
+    let started = {
+        let func_payload = function_context(&call.req, &call.info, &call.func, &call.method, &[]);
+        stream_request(state, &call.func, call.trace, func_payload)
+    };
+
+    let stream = match started {
+        Ok(stream) => stream,
+        Err(e) => return Box::new(ok(call.respond(Err(e)))),
+    };
+
+    let limit = call.limit;
+
+    let body = payload.fold((stream, 0), move |(mut stream, len), chunk: Bytes| {
+        let len = len + chunk.len();
+
+        if len > limit {
+            return Err(BodyError::TooLarge);
+        }
+
+        stream
+            .write_chunk(&chunk)
+            .map_err(|e| BodyError::Function(e))?;
+
+        Ok((stream, len))
+    });
+
+    Box::new(body.then(move |body| match body {
+        Ok((stream, _)) => Ok(if call.func.stream_response {
+            call.respond_streaming(stream.finish_streaming())
+        } else {
+            call.respond(stream.finish())
+        }),
+        Err(e) => call.body_error(e),
+    }))
+}
+
+/// The same response without the body, used to answer a HEAD with the response to a GET
+fn without_body(res: HttpResponse) -> HttpResponse {
+    let mut head_res = HttpResponse::build(res.status());
+
+    for (name, value) in res.headers().iter() {
+        head_res.header(name.clone(), value.clone());
+    }
+
+    head_res.finish()
+}
+
+/// Length of the body declared by the Content-Length header
+fn content_length(req: &HttpRequest) -> Option<usize> {
+    req.headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// The id of the request from the x-request-id header, or a new one when the header isn't set or
+/// isn't a valid id
+fn request_id(req: &HttpRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_request_id(value))
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// The id is echoed in the response, logged and passed to the function, so it's limited to letters,
+/// numbers, ".", "_" and "-"
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+}
+
+fn method_not_allowed(allow: &str) -> HttpResponse {
+    HttpResponse::MethodNotAllowed()
+        .header(header::ALLOW, allow)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use actix_web::web;
+    use fn_core::config::HeaderFilter;
+    use fn_core::secrets::EnvProvider;
+    use fn_core::state::State;
+    use std::collections::HashMap;
+
+    fn request_id_of(header: &str) -> String {
+        request_id(
+            &TestRequest::default()
+                .header(REQUEST_ID_HEADER, header)
+                .to_http_request(),
+        )
+    }
+
+    #[test]
+    fn request_id_from_the_header() {
+        let longest = "a".repeat(MAX_REQUEST_ID_LEN);
+
+        for id in &["abc-123_4.5", longest.as_str()] {
+            assert_eq!(&request_id_of(id), id);
+        }
+    }
+
+    #[test]
+    fn invalid_request_ids_are_replaced() {
+        let too_long = "a".repeat(MAX_REQUEST_ID_LEN + 1);
+
+        for id in &["", "a b", "a/b", "<script>", "é", too_long.as_str()] {
+            let generated = request_id_of(id);
+
+            assert_ne!(&generated, id);
+            assert!(Uuid::parse_str(&generated).is_ok());
+        }
+    }
+
+    /// The headers and cookies passed to the function for a request with cookies
+    fn forwarded(forward_headers: HeaderFilter) -> (Option<String>, HashMap<String, String>) {
+        let req = TestRequest::default()
+            .header("cookie", "session=abc; theme=dark")
+            .to_http_request();
+        let info = RequestInfo::new(&req);
+        let mut config = FunctionConfig::new(
+            "hello".to_string(),
+            "GET".into(),
+            "/hello".to_string(),
+            "hello.sh".to_string(),
+            None,
+            "unix_socket".to_string(),
+        );
+        config.forward_headers = forward_headers;
+
+        let ctx = function_context(&req, &info, &config, "GET", &[]);
+
+        (
+            ctx.req.headers.get("cookie").map(|v| v.to_string()),
+            ctx.req.cookies,
+        )
+    }
+
+    #[test]
+    fn cookies_are_passed_with_the_cookie_header() {
+        let (header, cookies) = forwarded(HeaderFilter::default());
+
+        assert_eq!(header, Some("session=abc; theme=dark".to_string()));
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies["session"], "abc");
+        assert_eq!(cookies["theme"], "dark");
+    }
+
+    #[test]
+    fn cookies_are_not_passed_when_the_cookie_header_is_filtered() {
+        let filters = vec![
+            HeaderFilter {
+                allow: None,
+                deny: vec!["Cookie".to_string()],
+            },
+            HeaderFilter {
+                allow: Some(vec!["accept".to_string()]),
+                deny: vec![],
+            },
+        ];
+
+        for filter in filters {
+            assert_eq!(forwarded(filter), (None, HashMap::new()));
+        }
+    }
+
+    #[test]
+    fn invoking_a_path_that_does_not_match_the_route_fails() {
+        let config = FunctionConfig::new(
+            "user".to_string(),
+            "GET".into(),
+            "/users/{id}".to_string(),
+            "user.sh".to_string(),
+            None,
+            "unix_socket".to_string(),
+        );
+        let state = web::Data::new(State::new(Arc::new(EnvProvider::new(""))));
+
+        let error = invoke(state, &config, "GET", "/accounts/42", &[]).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Path /accounts/42 does not match the route /users/{id}"
+        );
+    }
 }
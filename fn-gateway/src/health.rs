@@ -0,0 +1,120 @@
+use actix::{Actor, AsyncContext, Context};
+use fn_api::{ConvertFunction, FunctionContext, FunctionRequest, FunctionResponse};
+use fn_core::config::{FunctionConfig, HealthCheckConfig};
+use fn_core::runtime::{restart, RuntimeManager};
+use fn_core::state::{AppData, FunctionStatus};
+use fn_core::tracing::Span;
+use log::{error, warn};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::handlers::find_runtime;
+
+/// Warms up the functions marked `warm` once the worker has started, then runs the health check of
+/// each function that has one. Runs on a worker's thread, against the runtimes of its state.
+pub(crate) struct HealthChecker {
+    pub state: AppData,
+    pub functions: Vec<FunctionConfig>,
+}
+
+impl Actor for HealthChecker {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        for func in self.functions.iter() {
+            if func.warm {
+                let func = func.clone();
+
+                // scheduled rather than run here, so that the worker starts accepting requests
+                ctx.run_later(Duration::from_millis(0), move |checker, _| {
+                    checker.warm(&func)
+                });
+            }
+
+            if let Some(check) = &func.health_check {
+                let func = func.clone();
+                let interval = Duration::from_secs(check.interval_secs);
+
+                ctx.run_interval(interval, move |checker, _| checker.check(&func));
+            }
+        }
+    }
+}
+
+impl HealthChecker {
+    /// Initialize the function, as its first request would
+    fn warm(&self, func: &FunctionConfig) {
+        let span = Span::start("warm up", None);
+
+        if let Err(e) = find_runtime(self.state.clone(), func, span.context()) {
+            error!("Failed to warm up function {}: {}", func.name(), e);
+        }
+    }
+
+    /// Restart the function's process when it has exited or fails its health check. A function that
+    /// is still cold is left alone, it's started by its first request.
+    fn check(&self, func: &FunctionConfig) {
+        let check = match &func.health_check {
+            Some(check) => check,
+            None => return,
+        };
+
+        let runtime = match self.state.handles.read().get(func.name()) {
+            Some(runtime) => runtime.clone(),
+            None => return,
+        };
+
+        let healthy = {
+            let lock_guard = runtime.read();
+            is_healthy(&*lock_guard, func, check)
+        };
+
+        if let Err(reason) = healthy {
+            warn!(
+                "Function {} failed its health check: {}",
+                func.name(),
+                reason
+            );
+
+            self.state.set_status(func.name(), FunctionStatus::Crashed);
+
+            let mut lock_guard = runtime.write();
+
+            if let Err(e) = restart(&self.state, func, &mut *lock_guard) {
+                error!("Failed to restart function {}: {}", func.name(), e);
+            }
+        } else if self.state.status(func.name()) == FunctionStatus::Crashed {
+            // recovered on its own, such as after a timeout
+            self.state.set_status(func.name(), FunctionStatus::Ready);
+        }
+    }
+}
+
+/// Send the health check to the function, the reason it's unhealthy when it isn't
+fn is_healthy(
+    runtime: &dyn RuntimeManager,
+    func: &FunctionConfig,
+    check: &HealthCheckConfig,
+) -> Result<(), String> {
+    if runtime.has_exited() {
+        return Err("the process has exited".to_string());
+    }
+
+    let request_id = Uuid::new_v4().to_string();
+    let method = check.method.to_uppercase();
+
+    let mut func_req = FunctionRequest::new(func.name(), &func.handler, &check.path, &method, "");
+    func_req.route = &func.route;
+    func_req.request_id = &request_id;
+
+    let ctx = FunctionContext::new(func_req, FunctionResponse::new());
+
+    let func_res = runtime.handle_request(ctx).map_err(|e| e.to_string())?;
+    let func_res = FunctionResponse::from_slice(&func_res).map_err(|e| e.to_string())?;
+
+    if func_res.status_code >= 500 {
+        return Err(format!("responded with status {}", func_res.status_code));
+    }
+
+    Ok(())
+}
@@ -1,28 +1,39 @@
+mod admin;
+mod errors;
 mod handlers;
+mod health;
+mod routes;
+mod streaming;
+mod websocket;
 
+use actix::Actor;
 use actix_service::NewService;
 use actix_web::dev::{MessageBody, ServiceRequest, ServiceResponse};
-use actix_web::http::Method;
 use actix_web::{web, App, Error};
+use admin::Admin;
 use failure::Fail;
 use fn_core::config::Config;
-use handlers::{get_handler, post_handler};
+use fn_core::state::AppData;
+use handlers::handler;
+pub use handlers::invoke;
+use health::HealthChecker;
+use routes::Route;
 
 #[derive(Debug, Fail)]
 pub enum Errors {
-    #[fail(
-        display = "Failed to convert method listed in config to axtix_web::http::Method: {}",
-        _0
-    )]
-    MethodError(String),
+    #[fail(display = "Runtime ({}) does not exist", _0)]
+    UnknownRuntime(String),
 
-    #[fail(
-        display = "A handler for this HTTP Method has not been implemented: {}",
-        _0
-    )]
-    UnimplementedMethod(String),
+    #[fail(display = "Function {} is a websocket route and can't be invoked", _0)]
+    NotInvocable(String),
+
+    #[fail(display = "Path {} does not match the route {}", _0, _1)]
+    UnmatchedPath(String, String),
 }
 
+/// Register a resource for each route in the config, the functions bound to the route are matched
+/// by method when a request comes in. The config is expected to have been checked with
+/// `Config::validate`.
 pub fn bootstrap_gateway<T, B>(mut app: App<T, B>, config: &Config) -> App<T, B>
 where
     B: MessageBody,
@@ -34,33 +45,37 @@ where
         InitError = (),
     >,
 {
-    for func in config.functions_iter() {
-        let method = Method::from_bytes(func.method.to_uppercase().as_bytes());
-
-        if method.is_err() {
-            panic!(Errors::MethodError(func.method.clone()));
-        }
-
-        let method = method.unwrap();
+    for route in Route::from_config(config) {
+        let pattern = route.pattern.clone();
 
-        match method {
-            Method::POST => {
-                app = app.service(
-                    web::resource(&func.route)
-                        .data(func.clone())
-                        .to_async(post_handler),
-                );
-            }
-            Method::GET => {
-                app = app.service(
-                    web::resource(&func.route)
-                        .data(func.clone())
-                        .to_async(get_handler),
-                );
-            }
-            _ => panic!(Errors::UnimplementedMethod(func.method.clone())),
-        }
+        app = app.service(web::resource(&pattern).data(route).to_async(handler));
     }
 
     app
 }
+
+/// Serve the admin API under `admin.prefix`, every request must have the token as a bearer token
+pub fn bootstrap_admin<T, B>(app: App<T, B>, config: &Config, token: String) -> App<T, B>
+where
+    B: MessageBody,
+    T: NewService<
+        Config = (),
+        Request = ServiceRequest,
+        Response = ServiceResponse<B>,
+        Error = Error,
+        InitError = (),
+    >,
+{
+    app.data(Admin::new(token, config))
+        .service(admin::service(&config.admin().prefix))
+}
+
+/// Warm up the functions marked `warm` and start running health checks against the functions of
+/// the state. Must be called from a worker's thread, once for each state.
+pub fn start_health_checks(state: AppData, config: &Config) {
+    HealthChecker {
+        state,
+        functions: config.functions().clone(),
+    }
+    .start();
+}
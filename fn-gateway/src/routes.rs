@@ -0,0 +1,67 @@
+use fn_core::config::{Config, FunctionConfig};
+
+/// Every function bound to a route, registered as a single resource so that the method can be
+/// matched against all of them
+#[derive(Clone)]
+pub(crate) struct Route {
+    pub route: String,
+    /// The route as registered with the router, see `FunctionConfig::route_pattern`
+    pub pattern: String,
+    pub functions: Vec<FunctionConfig>,
+    /// Include the details of errors in responses, see `GatewayConfig::expose_errors`
+    pub expose_errors: bool,
+    /// Largest request body accepted when the function doesn't set its own limit
+    pub max_body_bytes: usize,
+}
+
+impl Route {
+    /// Group the functions by route, in the order that the routes first appear in the config
+    pub fn from_config(config: &Config) -> Vec<Route> {
+        let mut routes: Vec<Route> = vec![];
+
+        for func in config.functions_iter() {
+            match routes.iter_mut().find(|route| route.route == func.route) {
+                Some(route) => route.functions.push(func.clone()),
+                None => routes.push(Route {
+                    route: func.route.clone(),
+                    pattern: func.route_pattern(),
+                    functions: vec![func.clone()],
+                    expose_errors: config.gateway().expose_errors,
+                    max_body_bytes: config.gateway().max_body_bytes,
+                }),
+            }
+        }
+
+        routes
+    }
+
+    /// The function that handles the method, which must be uppercase
+    pub fn find(&self, method: &str) -> Option<&FunctionConfig> {
+        self.functions
+            .iter()
+            .find(|func| func.method.contains(method))
+    }
+
+    /// Every method allowed on the route, used for the Allow header
+    pub fn allowed_methods(&self) -> Vec<String> {
+        let mut methods: Vec<String> = vec![];
+
+        for func in self.functions.iter() {
+            for method in func.method.list() {
+                if !methods.contains(&method) {
+                    methods.push(method);
+                }
+            }
+        }
+
+        if methods.iter().any(|method| method == "GET") && !methods.iter().any(|m| m == "HEAD") {
+            methods.push("HEAD".to_string());
+        }
+
+        if !methods.iter().any(|method| method == "OPTIONS") {
+            methods.push("OPTIONS".to_string());
+        }
+
+        methods
+    }
+}
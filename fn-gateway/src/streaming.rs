@@ -0,0 +1,64 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::Error;
+use bytes::Bytes;
+use fn_api::FunctionResponse;
+use futures::sync::mpsc;
+use futures::{Sink, Stream};
+use log::error;
+use std::thread;
+
+/// How many chunks can be waiting to be sent to the client before the function is paused
+const CHUNK_BUFFER: usize = 16;
+
+/// Content type of server-sent events
+pub(crate) const EVENT_STREAM: &str = "text/event-stream";
+
+type Chunks = Box<dyn Iterator<Item = Result<Vec<u8>, failure::Error>> + Send>;
+
+/// Forward the chunks of a streamed response to the client. Reading a chunk blocks until the
+/// function sends it, so the chunks are read on their own thread and passed to the response
+/// through a channel. If the function fails partway through, the response is cut off so that the
+/// client can tell that it's incomplete.
+pub(crate) fn body_stream(
+    function: String,
+    request_id: String,
+    chunks: Chunks,
+) -> impl Stream<Item = Bytes, Error = Error> {
+    let (tx, rx) = mpsc::channel::<Result<Bytes, ()>>(CHUNK_BUFFER);
+
+    thread::spawn(move || {
+        let mut tx = tx.wait();
+
+        for chunk in chunks {
+            let chunk = match chunk {
+                Ok(chunk) => Ok(Bytes::from(chunk)),
+                Err(e) => {
+                    error!(
+                        "[{}] function {} failed while streaming its response: {}",
+                        request_id, function, e
+                    );
+                    Err(())
+                }
+            };
+
+            let failed = chunk.is_err();
+
+            // the client went away, dropping the chunks closes the connection to the function
+            if tx.send(chunk).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    rx.then(|chunk| match chunk {
+        Ok(Ok(chunk)) => Ok(chunk),
+        _ => Err(ErrorInternalServerError("function failed while streaming")),
+    })
+}
+
+/// Whether the function is sending server-sent events
+pub(crate) fn is_event_stream(res: &FunctionResponse) -> bool {
+    res.headers
+        .get("content-type")
+        .map_or(false, |content_type| content_type.starts_with(EVENT_STREAM))
+}
@@ -0,0 +1,264 @@
+use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, Message, StreamHandler};
+use actix_web::web::Payload;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use fn_api::headers::Headers;
+use fn_api::websocket::{WebSocketContext, WebSocketEvent};
+use fn_api::FunctionRequest;
+use fn_core::config::FunctionConfig;
+use fn_core::runtime::{MessageHandler, WebSocketMessage};
+use fn_core::state::AppData;
+use fn_core::tracing::SpanContext;
+use log::error;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use uuid::Uuid;
+
+use crate::handlers::{connect_websocket, function_context, RequestInfo};
+
+/// Accept the websocket handshake and start a session with the function
+pub(crate) fn start(
+    state: AppData,
+    req: &HttpRequest,
+    func: FunctionConfig,
+    trace: SpanContext,
+    payload: Payload,
+) -> Result<HttpResponse, Error> {
+    let session = WebSocketSession {
+        state,
+        handshake: Some(Handshake::new(req, RequestInfo::new(req), func)),
+        trace,
+        events: None,
+    };
+
+    ws::start(session, req, payload)
+}
+
+/// A message pushed by the function, delivered to the session on the gateway's thread
+struct Push(WebSocketMessage);
+
+impl Message for Push {
+    type Result = ();
+}
+
+/// The connection to the function failed, the websocket is closed
+struct Failed;
+
+impl Message for Failed {
+    type Result = ();
+}
+
+/// An event for the function, with the message when the client sent one
+struct Event {
+    event: WebSocketEvent,
+    body: Vec<u8>,
+    is_binary: bool,
+}
+
+/// The handshake request, passed to the function with every event. The values that the
+/// FunctionRequest borrows from the HttpRequest are kept, so that it can be built on another thread
+struct Handshake {
+    info: RequestInfo,
+    func: FunctionConfig,
+    path: String,
+    query_string: String,
+    headers: Headers,
+    cookies: HashMap<String, String>,
+    query: HashMap<String, Vec<String>>,
+    params: HashMap<String, String>,
+}
+
+impl Handshake {
+    fn new(req: &HttpRequest, info: RequestInfo, func: FunctionConfig) -> Handshake {
+        let func_req = function_context(req, &info, &func, "GET", &[]).req;
+
+        let (headers, cookies, query, params) = (
+            func_req.headers,
+            func_req.cookies,
+            func_req.query,
+            func_req.params,
+        );
+
+        Handshake {
+            info,
+            func,
+            path: req.path().to_string(),
+            query_string: req.query_string().to_string(),
+            headers,
+            cookies,
+            query,
+            params,
+        }
+    }
+
+    fn request(&self) -> FunctionRequest {
+        let mut func_req = FunctionRequest::new(
+            self.func.name(),
+            &self.func.handler,
+            &self.path,
+            "GET",
+            &self.query_string,
+        );
+
+        func_req.headers = self.headers.clone();
+        func_req.cookies = self.cookies.clone();
+        func_req.query = self.query.clone();
+        func_req.route = &self.func.route;
+        func_req.params = self.params.clone();
+        self.info.describe(&mut func_req);
+
+        func_req
+    }
+}
+
+/// A websocket client connected to a function. The session opens a connection to the function when
+/// it starts, then sends it an event when the client connects, for each message from the client
+/// and when either side disconnects. Connecting and sending can block on the function, so they're
+/// done by a thread of the session rather than on the gateway's thread.
+struct WebSocketSession {
+    state: AppData,
+    /// Moved to the session's thread when it starts
+    handshake: Option<Handshake>,
+    /// Context of the handshake's span, the connection to the function is made within its trace
+    trace: SpanContext,
+    /// Queues the events for the session's thread, dropping it ends the thread
+    events: Option<Sender<Event>>,
+}
+
+impl WebSocketSession {
+    /// Queue the event for the function, nothing is sent once the connection has failed
+    fn send(&mut self, event: WebSocketEvent, body: &[u8], is_binary: bool) {
+        if let Some(events) = &self.events {
+            let _ = events.send(Event {
+                event,
+                body: body.to_vec(),
+                is_binary,
+            });
+        }
+    }
+}
+
+impl Actor for WebSocketSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let handshake = match self.handshake.take() {
+            Some(handshake) => handshake,
+            None => return,
+        };
+
+        let (events, received) = mpsc::channel();
+        let state = self.state.clone();
+        let trace = self.trace;
+        let session = ctx.address();
+
+        thread::spawn(move || forward_events(state, handshake, trace, session, received));
+
+        self.events = Some(events);
+        self.send(WebSocketEvent::Connect, &[], false);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.send(WebSocketEvent::Disconnect, &[], false);
+
+        // the thread closes the connection to the function once it has sent the disconnect
+        self.events = None;
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for WebSocketSession {
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        match msg {
+            ws::Message::Ping(msg) => ctx.pong(&msg),
+            ws::Message::Text(text) => self.send(WebSocketEvent::Message, text.as_bytes(), false),
+            ws::Message::Binary(bin) => self.send(WebSocketEvent::Message, &bin, true),
+            ws::Message::Close(_) => {
+                ctx.close(None);
+                ctx.stop();
+            }
+            ws::Message::Pong(_) | ws::Message::Nop => {}
+        }
+    }
+}
+
+impl Handler<Push> for WebSocketSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: Push, ctx: &mut Self::Context) {
+        match msg.0 {
+            WebSocketMessage::Text(text) => ctx.text(text),
+            WebSocketMessage::Binary(bin) => ctx.binary(bin),
+            WebSocketMessage::Close => {
+                ctx.close(None);
+                ctx.stop();
+            }
+        }
+    }
+}
+
+impl Handler<Failed> for WebSocketSession {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Failed, ctx: &mut Self::Context) {
+        self.events = None;
+
+        ctx.close(Some(ws::CloseCode::Error.into()));
+        ctx.stop();
+    }
+}
+
+/// Connect to the function, then send it the events of the session in order until the session
+/// stops. The session is told when either fails.
+fn forward_events(
+    state: AppData,
+    handshake: Handshake,
+    trace: SpanContext,
+    session: Addr<WebSocketSession>,
+    events: Receiver<Event>,
+) {
+    let func = &handshake.func;
+    let request_id = &handshake.info.request_id;
+
+    let pushed_to = session.clone();
+    let on_message: MessageHandler = Box::new(move |message| pushed_to.do_send(Push(message)));
+
+    let mut connection = match connect_websocket(state, func, trace, on_message) {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!(
+                "[{}] failed to connect to function {}: {}",
+                request_id,
+                func.name(),
+                e
+            );
+
+            session.do_send(Failed);
+            return;
+        }
+    };
+
+    let req = handshake.request();
+    let connection_id = Uuid::new_v4().to_string();
+
+    // ends once the session has stopped and its events have been sent, dropping the connection
+    // closes it
+    for event in events {
+        let mut context = WebSocketContext::new(event.event, &connection_id, req.clone());
+        context.body = &event.body;
+        context.is_binary = event.is_binary;
+
+        if let Err(e) = connection.send(context) {
+            error!(
+                "[{}] failed to send the {:?} event to function {}: {}",
+                request_id,
+                event.event,
+                func.name(),
+                e
+            );
+
+            session.do_send(Failed);
+            return;
+        }
+    }
+}
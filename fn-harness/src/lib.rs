@@ -0,0 +1,180 @@
+//! Run a function against its real runtime without the HTTP server, so that functions can be
+//! tested with `cargo test`.
+//!
+//! ```no_run
+//! use fn_harness::Harness;
+//!
+//! let harness = Harness::unix_socket("examples/node/index.js", Some("node")).unwrap();
+//!
+//! harness
+//!     .post("/users")
+//!     .json(&serde_json::json!({ "name": "kyle" }))
+//!     .send()
+//!     .unwrap()
+//!     .assert_status(201)
+//!     .assert_header("content-type", "application/json");
+//! ```
+
+mod request;
+mod response;
+
+pub use request::TestRequest;
+pub use response::TestResponse;
+
+use failure::Fail;
+use fn_api::{ConvertFunction, FunctionContext, FunctionResponse};
+use fn_core::config::{FunctionConfig, Methods, RouteType};
+use fn_core::runtime::RuntimeManager;
+use fn_core::secrets::{SecretError, SecretProvider};
+use fn_unix_socket_runtime::runtime::UnixSocketRuntime;
+use fn_wasm_runtime::runtime::WasmRuntime;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+#[derive(Debug, Fail)]
+pub enum HarnessError {
+    #[fail(display = "Runtime ({}) does not exist", _0)]
+    UnknownRuntime(String),
+
+    #[fail(display = "Failed to resolve the secrets of the function: {}", _0)]
+    SecretError(SecretError),
+
+    #[fail(display = "Failed to start the function: {}", _0)]
+    StartupError(failure::Error),
+
+    #[fail(display = "Websocket functions can't be invoked")]
+    WebSocketUnsupported,
+
+    #[fail(display = "Failed to invoke the function: {}", _0)]
+    InvokeError(failure::Error),
+
+    #[fail(display = "Failed to serialize the body as JSON: {}", _0)]
+    JsonError(serde_json::Error),
+}
+
+/// A running function. The runtime is started when the harness is created and shut down when it's
+/// dropped, each request is sent straight to the runtime.
+pub struct Harness {
+    config: FunctionConfig,
+    runtime: Arc<RwLock<dyn RuntimeManager>>,
+}
+
+impl Harness {
+    /// Start the runtime of the function, any secrets it references are left unresolved
+    pub fn start(config: FunctionConfig) -> Result<Harness, HarnessError> {
+        let runtime: Arc<RwLock<dyn RuntimeManager>> = match config.runtime.as_str() {
+            "unix_socket" => {
+                UnixSocketRuntime::initialize(&config).map_err(|e| HarnessError::StartupError(e))?
+            }
+            "wasm" => {
+                WasmRuntime::initialize(&config).map_err(|e| HarnessError::StartupError(e))?
+            }
+            _ => Err(HarnessError::UnknownRuntime(config.runtime.clone()))?,
+        };
+
+        Ok(Harness { config, runtime })
+    }
+
+    /// Resolve the secrets referenced by the config with the provider, then start the runtime
+    pub fn start_with_secrets(
+        mut config: FunctionConfig,
+        provider: &dyn SecretProvider,
+    ) -> Result<Harness, HarnessError> {
+        config
+            .resolve_secrets(provider)
+            .map_err(|e| HarnessError::SecretError(e))?;
+
+        Harness::start(config)
+    }
+
+    /// Start a unix socket function, the handler is executed with `cmd` when it's set (ex: node)
+    pub fn unix_socket(handler: &str, cmd: Option<&str>) -> Result<Harness, HarnessError> {
+        Harness::start(Harness::config(handler, cmd, "unix_socket"))
+    }
+
+    /// Start a wasm function
+    pub fn wasm(handler: &str) -> Result<Harness, HarnessError> {
+        Harness::start(Harness::config(handler, None, "wasm"))
+    }
+
+    /// Config of a function that handles every method on `/`
+    fn config(handler: &str, cmd: Option<&str>, runtime: &str) -> FunctionConfig {
+        FunctionConfig::new(
+            String::new(),
+            Methods::One("*".to_string()),
+            "/".to_string(),
+            handler.to_string(),
+            cmd.map(|cmd| cmd.to_string()),
+            runtime.to_string(),
+        )
+    }
+
+    pub fn function_config(&self) -> &FunctionConfig {
+        &self.config
+    }
+
+    pub fn request(&self, method: &str, path: &str) -> TestRequest {
+        TestRequest::new(self, method, path)
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest {
+        self.request("GET", path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest {
+        self.request("POST", path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest {
+        self.request("PUT", path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest {
+        self.request("PATCH", path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest {
+        self.request("DELETE", path)
+    }
+
+    /// Send the context to the runtime and decode its response, a streamed response is collected
+    /// into the body
+    fn invoke(&self, ctx: FunctionContext, body: &[u8]) -> Result<TestResponse, HarnessError> {
+        if self.config.route_type == RouteType::WebSocket {
+            return Err(HarnessError::WebSocketUnsupported);
+        }
+
+        let lock_guard = self.runtime.read();
+
+        let res = match (self.config.stream_body, self.config.stream_response) {
+            (false, false) => lock_guard.handle_request(ctx).and_then(|res| decode(&res)),
+            (false, true) => lock_guard
+                .handle_streaming(ctx)
+                .and_then(|res| res.collect()),
+            (true, stream_response) => lock_guard.stream_request(ctx).and_then(|mut stream| {
+                stream.write_chunk(body)?;
+
+                if stream_response {
+                    stream.finish_streaming()?.collect()
+                } else {
+                    decode(&stream.finish()?)
+                }
+            }),
+        };
+
+        res.map(TestResponse::new)
+            .map_err(|e| HarnessError::InvokeError(e))
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        if let Err(e) = self.runtime.write().shutdown() {
+            println!("Failed to shut down function {}: {}", self.config.name(), e);
+        }
+    }
+}
+
+fn decode(res: &[u8]) -> Result<FunctionResponse, failure::Error> {
+    Ok(FunctionResponse::from_slice(res)?)
+}
@@ -0,0 +1,113 @@
+use fn_api::{FunctionContext, FunctionRequest, FunctionResponse};
+use fn_core::query;
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::{Harness, HarnessError, TestResponse};
+
+/// A request to the function, built up before it's sent
+pub struct TestRequest<'h> {
+    harness: &'h Harness,
+    method: String,
+    path: String,
+    query_string: String,
+    headers: Vec<(String, String)>,
+    cookies: HashMap<String, String>,
+    params: HashMap<String, String>,
+    body: Vec<u8>,
+    /// Serializing the JSON body failed, reported when the request is sent
+    error: Option<HarnessError>,
+}
+
+impl<'h> TestRequest<'h> {
+    pub(crate) fn new(harness: &'h Harness, method: &str, path: &str) -> TestRequest<'h> {
+        let (path, query_string) = match path.find('?') {
+            Some(index) => (&path[..index], &path[index + 1..]),
+            None => (path, ""),
+        };
+
+        TestRequest {
+            harness,
+            method: method.to_uppercase(),
+            path: path.to_string(),
+            query_string: query_string.to_string(),
+            headers: vec![],
+            cookies: HashMap::new(),
+            params: HashMap::new(),
+            body: vec![],
+            error: None,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> TestRequest<'h> {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn cookie(mut self, name: &str, value: &str) -> TestRequest<'h> {
+        self.cookies.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// A parameter captured by the route, such as `id` from `/users/{id}`
+    pub fn param(mut self, name: &str, value: &str) -> TestRequest<'h> {
+        self.params.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> TestRequest<'h> {
+        self.body = body.into();
+        self
+    }
+
+    /// Serialize the value as the body, with an application/json content-type
+    pub fn json<T: Serialize>(mut self, value: &T) -> TestRequest<'h> {
+        match serde_json::to_vec(value) {
+            Ok(body) => self.body = body,
+            Err(e) => self.error = Some(HarnessError::JsonError(e)),
+        }
+
+        self.header("content-type", "application/json")
+    }
+
+    /// Send the request to the function and wait for its response
+    pub fn send(self) -> Result<TestResponse, HarnessError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let config = self.harness.function_config();
+        let request_id = Uuid::new_v4().to_string();
+
+        let mut func_req = FunctionRequest::new(
+            config.name(),
+            &config.handler,
+            &self.path,
+            &self.method,
+            &self.query_string,
+        );
+
+        for (name, value) in self.headers.iter() {
+            func_req.headers.append(name, value);
+        }
+
+        func_req.cookies = self.cookies.clone();
+        func_req.query = query::parse(&self.query_string);
+        func_req.route = &config.route;
+        func_req.params = self.params.clone();
+        func_req.request_id = &request_id;
+        func_req.version = "HTTP/1.1";
+        func_req.scheme = "http";
+        func_req.host = "localhost";
+
+        // a streamed body is written after the request has started
+        if !config.stream_body {
+            func_req.body = &self.body;
+        }
+
+        let ctx = FunctionContext::new(func_req, FunctionResponse::new());
+
+        self.harness.invoke(ctx, &self.body)
+    }
+}
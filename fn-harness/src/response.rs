@@ -0,0 +1,125 @@
+use fn_api::FunctionResponse;
+use serde::de::DeserializeOwned;
+
+/// The function's response, with assertions that panic with the response in the message so that
+/// a failing test shows what the function returned
+pub struct TestResponse {
+    response: FunctionResponse,
+}
+
+impl TestResponse {
+    pub(crate) fn new(response: FunctionResponse) -> TestResponse {
+        TestResponse { response }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.response.status_code
+    }
+
+    /// The first value of the header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.response.headers.get(name)
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.response.body
+    }
+
+    /// The body as UTF-8, invalid sequences are replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.response.body).to_string()
+    }
+
+    /// Deserialize the body from JSON, panics if it isn't valid
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.response.body)
+            .unwrap_or_else(|e| panic!("expected a JSON body: {}\nbody: {}", e, self.text()))
+    }
+
+    /// The whole response the function returned
+    pub fn into_inner(self) -> FunctionResponse {
+        self.response
+    }
+
+    pub fn assert_status(&self, status: u16) -> &TestResponse {
+        if self.status() != status {
+            self.fail(&format!(
+                "expected status {}, found {}",
+                status,
+                self.status()
+            ));
+        }
+
+        self
+    }
+
+    /// The status is 2xx
+    pub fn assert_success(&self) -> &TestResponse {
+        if self.status() < 200 || self.status() >= 300 {
+            self.fail(&format!("expected a 2xx status, found {}", self.status()));
+        }
+
+        self
+    }
+
+    pub fn assert_header(&self, name: &str, value: &str) -> &TestResponse {
+        let values = self.response.headers.get_all(name);
+
+        if !values.iter().any(|v| v == value) {
+            self.fail(&format!(
+                "expected header {} to be \"{}\", found {:?}",
+                name, value, values
+            ));
+        }
+
+        self
+    }
+
+    pub fn assert_no_header(&self, name: &str) -> &TestResponse {
+        if self.response.headers.contains(name) {
+            self.fail(&format!("expected no {} header", name));
+        }
+
+        self
+    }
+
+    pub fn assert_body<B: AsRef<[u8]>>(&self, body: B) -> &TestResponse {
+        if self.body() != body.as_ref() {
+            self.fail(&format!(
+                "expected body \"{}\"",
+                String::from_utf8_lossy(body.as_ref())
+            ));
+        }
+
+        self
+    }
+
+    pub fn assert_body_contains(&self, text: &str) -> &TestResponse {
+        if !self.text().contains(text) {
+            self.fail(&format!("expected the body to contain \"{}\"", text));
+        }
+
+        self
+    }
+
+    /// The body is JSON equal to the value
+    pub fn assert_json(&self, value: serde_json::Value) -> &TestResponse {
+        let body: serde_json::Value = self.json();
+
+        if body != value {
+            self.fail(&format!("expected JSON body {}", value));
+        }
+
+        self
+    }
+
+    fn fail(&self, message: &str) -> ! {
+        panic!(
+            "{}\nstatus: {}\nheaders: {:?}\nbody: {}",
+            message,
+            self.status(),
+            self.response.headers.iter().collect::<Vec<(&str, &str)>>(),
+            self.text()
+        );
+    }
+}
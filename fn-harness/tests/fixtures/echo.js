@@ -0,0 +1,49 @@
+// Responds with the request it received as JSON, so that the tests can check what the harness
+// sends. Has no dependencies, unlike the example in examples/node
+const net = require('net');
+
+function respond(req) {
+  // a fixed body that isn't valid UTF-8, so it has to be base64 encoded
+  if (req.path === '/binary') {
+    return {
+      status_code: 200,
+      headers: { 'content-type': 'application/octet-stream' },
+      body: Buffer.from([0, 159, 146, 150]).toString('base64'),
+      is_base64_encoded: true,
+    };
+  }
+
+  const body = req.is_base64_encoded ? Buffer.from(req.body, 'base64').toString() : req.body;
+
+  return {
+    status_code: req.query.status ? Number(req.query.status[0]) : 200,
+    headers: { 'content-type': 'application/json', 'x-method': req.method },
+    body: JSON.stringify({
+      method: req.method,
+      path: req.path,
+      query: req.query,
+      headers: req.headers,
+      cookies: req.cookies,
+      params: req.params,
+      body,
+    }),
+  };
+}
+
+// the harness shuts down its side once the whole request is written, so the connection stays half
+// open while the response is written
+const server = net.createServer({ allowHalfOpen: true }, (client) => {
+  const chunks = [];
+
+  client.on('data', (chunk) => chunks.push(chunk));
+
+  client.on('end', () => {
+    const { req } = JSON.parse(Buffer.concat(chunks).toString());
+
+    client.end(JSON.stringify(respond(req)));
+  });
+});
+
+server.listen(process.argv[2]);
+
+process.on('SIGTERM', () => process.exit());
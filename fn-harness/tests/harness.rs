@@ -0,0 +1,137 @@
+use fn_harness::Harness;
+use serde_json::{json, Value};
+
+/// Path of a file relative to the root of the crate, the tests may run from another directory
+fn path(relative: &str) -> String {
+    format!("{}/{}", env!("CARGO_MANIFEST_DIR"), relative)
+}
+
+/// Responds with the request it received, see tests/fixtures/echo.js. The tests that use it need
+/// node, so they're ignored unless run with `cargo test -- --ignored`
+fn echo() -> Harness {
+    Harness::unix_socket(&path("tests/fixtures/echo.js"), Some("node")).unwrap()
+}
+
+#[test]
+#[ignore]
+fn sends_the_request() {
+    let harness = echo();
+
+    let res = harness
+        .get("/users?name=kyle&tag=a&tag=b")
+        .header("X-Test", "1")
+        .cookie("session", "abc")
+        .param("id", "7")
+        .send()
+        .unwrap();
+
+    res.assert_status(200)
+        .assert_header("content-type", "application/json")
+        .assert_header("x-method", "GET");
+
+    let req: Value = res.json();
+
+    assert_eq!(req["method"], "GET");
+    assert_eq!(req["path"], "/users");
+    assert_eq!(req["query"], json!({ "name": ["kyle"], "tag": ["a", "b"] }));
+    assert_eq!(req["headers"]["x-test"], json!(["1"]));
+    assert_eq!(req["cookies"], json!({ "session": "abc" }));
+    assert_eq!(req["params"], json!({ "id": "7" }));
+    assert_eq!(req["body"], "");
+}
+
+#[test]
+#[ignore]
+fn sends_a_json_body() {
+    let harness = echo();
+
+    let res = harness
+        .post("/users")
+        .json(&json!({ "name": "kyle" }))
+        .send()
+        .unwrap();
+
+    let req: Value = res.assert_success().json();
+
+    assert_eq!(req["method"], "POST");
+    assert_eq!(req["headers"]["content-type"], json!(["application/json"]));
+    assert_eq!(req["body"], r#"{"name":"kyle"}"#);
+}
+
+#[test]
+#[ignore]
+fn uses_the_status_of_the_function() {
+    let harness = echo();
+
+    harness
+        .request("delete", "/?status=204")
+        .send()
+        .unwrap()
+        .assert_status(204)
+        .assert_success()
+        .assert_header("x-method", "DELETE")
+        .assert_no_header("set-cookie");
+}
+
+#[test]
+#[ignore]
+fn decodes_a_base64_body() {
+    let harness = echo();
+
+    let res = harness.get("/binary").send().unwrap();
+
+    res.assert_header("content-type", "application/octet-stream")
+        .assert_body(&[0u8, 159, 146, 150][..]);
+}
+
+#[test]
+#[ignore]
+#[should_panic(expected = "expected status 404, found 200")]
+fn failed_assertions_panic() {
+    echo().get("/").send().unwrap().assert_status(404);
+}
+
+#[test]
+#[ignore]
+#[should_panic(expected = "expected the body to contain \"missing\"")]
+fn failed_body_assertions_show_the_body() {
+    echo()
+        .get("/")
+        .send()
+        .unwrap()
+        .assert_body_contains("missing");
+}
+
+// needs the dependencies of the example, `yarn install` in examples/node
+#[test]
+#[ignore]
+fn node_example() {
+    let harness = Harness::unix_socket(&path("../examples/node/index.js"), Some("node")).unwrap();
+
+    harness.get("/").send().unwrap().assert_body("hello");
+    harness
+        .get("/?name=kyle")
+        .send()
+        .unwrap()
+        .assert_status(200)
+        .assert_body("hello kyle");
+}
+
+// needs the example to be built, `cargo build --target wasm32-unknown-unknown` in examples/wasm
+#[test]
+#[ignore]
+fn wasm_example() {
+    let harness = Harness::wasm(&path(
+        "../examples/wasm/target/wasm32-unknown-unknown/debug/wasm.wasm",
+    ))
+    .unwrap();
+
+    harness
+        .get("/?name=kyle")
+        .send()
+        .unwrap()
+        .assert_status(200)
+        .assert_header("x-test", "abc")
+        .assert_header("x-name", "kyle")
+        .assert_body("hello from wasm -- test");
+}
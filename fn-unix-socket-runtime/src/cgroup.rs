@@ -0,0 +1,155 @@
+use fn_core::config::CgroupConfig;
+use fn_core::runtime::ResourceUsage;
+use log::warn;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Controllers that need to be enabled for the limits to be applied
+const CONTROLLERS: &str = "+memory +cpu +pids";
+
+/// A cgroup v2 created for a single process of a function. The cgroup is removed when dropped, any
+/// process still in it is killed first.
+pub struct Cgroup {
+    path: PathBuf,
+    procs: File,
+}
+
+impl Cgroup {
+    /// Create the cgroup and apply the limits. When cgroups aren't available or writable, such as on
+    /// a dev machine, the reason is logged and none is returned so the function runs without it.
+    /// The name has to be unique to the process, such as to the worker's process of the function,
+    /// or the processes would share the limits and the usage.
+    pub fn create(name: &str, config: &CgroupConfig) -> Option<Cgroup> {
+        match Cgroup::try_create(name, config) {
+            Ok(cgroup) => Some(cgroup),
+            Err(e) => {
+                warn!(
+                    "Failed to create the cgroup for {} in {}, running without it: {}",
+                    name, config.root, e
+                );
+                None
+            }
+        }
+    }
+
+    fn try_create(name: &str, config: &CgroupConfig) -> io::Result<Cgroup> {
+        let root = Path::new(&config.root);
+
+        fs::create_dir_all(root)?;
+
+        // the controllers have to be enabled in every ancestor, the parent of the root may already
+        // have them or not be writable in which case enabling them in the root will fail
+        if let Some(parent) = root.parent() {
+            let _ = write_file(&parent.join("cgroup.subtree_control"), CONTROLLERS);
+        }
+
+        write_file(&root.join("cgroup.subtree_control"), CONTROLLERS)?;
+
+        let path = root.join(name);
+
+        if !path.exists() {
+            fs::create_dir(&path)?;
+        }
+
+        if let Some(memory_max) = config.memory_max {
+            write_file(&path.join("memory.max"), &memory_max.to_string())?;
+        }
+
+        if let Some(cpu_max) = &config.cpu_max {
+            write_file(&path.join("cpu.max"), cpu_max)?;
+        }
+
+        if let Some(pids_max) = config.pids_max {
+            write_file(&path.join("pids.max"), &pids_max.to_string())?;
+        }
+
+        let procs = OpenOptions::new()
+            .write(true)
+            .open(path.join("cgroup.procs"))?;
+
+        Ok(Cgroup { path, procs })
+    }
+
+    /// Move the process into the cgroup before it execs, so that it can't start any processes
+    /// outside of the cgroup. Writing 0 to cgroup.procs moves the process that writes it.
+    pub fn apply(&self, command: &mut Command) {
+        let fd = self.procs.as_raw_fd();
+
+        unsafe {
+            command.pre_exec(move || {
+                if libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+    }
+
+    /// Read the usage back from the cgroup's files, anything that can't be read is left unset
+    pub fn usage(&self) -> ResourceUsage {
+        let cpu_usec = fs::read_to_string(self.path.join("cpu.stat"))
+            .ok()
+            .and_then(|stat| {
+                stat.lines()
+                    .find(|line| line.starts_with("usage_usec "))
+                    .and_then(|line| line["usage_usec ".len()..].trim().parse().ok())
+            });
+
+        ResourceUsage {
+            memory_bytes: read_u64(&self.path.join("memory.current")),
+            cpu_usec,
+            pids: read_u64(&self.path.join("pids.current")),
+        }
+    }
+
+    /// Kill anything left in the cgroup, such as processes the handler started in the background,
+    /// since a cgroup can only be removed once it's empty
+    fn remove(&self) -> io::Result<()> {
+        if !self.is_empty() {
+            // cgroup.kill is only available from Linux 5.14
+            let _ = write_file(&self.path.join("cgroup.kill"), "1");
+
+            let started = Instant::now();
+
+            while !self.is_empty() && started.elapsed() < Duration::from_secs(1) {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        fs::remove_dir(&self.path)
+    }
+
+    fn is_empty(&self) -> bool {
+        fs::read_to_string(self.path.join("cgroup.procs"))
+            .map(|procs| procs.trim().is_empty())
+            .unwrap_or(true)
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        if let Err(e) = self.remove() {
+            warn!("Failed to remove the cgroup {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| contents.trim().parse().ok())
+}
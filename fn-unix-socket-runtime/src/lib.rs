@@ -1,2 +1,6 @@
+mod cgroup;
 pub mod runtime;
+mod sandbox;
 mod socket;
+mod stream;
+mod websocket;
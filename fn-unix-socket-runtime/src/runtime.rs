@@ -1,11 +1,24 @@
+use crate::cgroup::Cgroup;
+use crate::sandbox;
 use crate::socket::{Socket, SocketError};
+use crate::stream::{FrameReader, SocketStream};
+use crate::websocket::SocketConnection;
 use fn_api::{ConvertFunction, FunctionContext, FunctionResponse};
 use fn_core::config::FunctionConfig;
-use fn_core::runtime::RuntimeManager;
+use fn_core::runtime::{
+    MessageHandler, ResourceUsage, RuntimeError, RuntimeManager, StreamingRequest,
+    StreamingResponse, WebSocketConnection,
+};
+use fn_core::tracing::Span;
 
+use nix::errno::Errno;
 use nix::sys::signal::{kill, Signal};
-use nix::unistd::Pid;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{chown, Gid, Pid, Uid};
 use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use failure::Fail;
 use nix::sys::socket::SockAddr;
@@ -14,6 +27,12 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use tempfile::TempDir;
 
+/// How long to wait for the function's socket to become writable and then readable
+const SOCKET_TIMEOUT_MS: i32 = 2500;
+
+/// How long the function has to exit after SIGTERM before it's killed, and then to be killed
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Fail)]
 pub enum UnixSocketError {
     #[fail(display = "Failed to create the temporary file")]
@@ -43,6 +62,15 @@ pub enum UnixSocketError {
     #[fail(display = "Failed to kill child process")]
     KillError(nix::Error),
 
+    #[fail(display = "The function's process is still running after it was killed")]
+    StillRunning,
+
+    #[fail(
+        display = "Failed to give the function's user access to the socket {}",
+        _0
+    )]
+    PermissionError(nix::Error),
+
     #[fail(display = "Failed to lock on handles")]
     LockError,
 
@@ -65,6 +93,9 @@ pub enum UnixSocketError {
 pub struct UnixSocketRuntime {
     config: FunctionConfig,
     process: Option<Child>,
+    /// Set once the process has been seen to exit, it can only be waited for once
+    exited: AtomicBool,
+    cgroup: Option<Cgroup>,
     _tempdir: TempDir,
     sock_name: PathBuf,
     sock_addr: SockAddr,
@@ -87,48 +118,125 @@ impl RuntimeManager for UnixSocketRuntime {
             sock_name,
             sock_addr,
             process: None,
+            exited: AtomicBool::new(false),
+            cgroup: None,
         };
 
         runtime.start()?;
 
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        thread::sleep(Duration::from_secs(1));
 
         Ok(Arc::new(RwLock::new(runtime)))
     }
 
     fn shutdown(&mut self) -> Result<(), failure::Error> {
-        match &mut self.process {
-            Some(process) => kill(Pid::from_raw(process.id() as i32), Signal::SIGTERM)
-                .map_err(|e| UnixSocketError::KillError(e))?,
-            _ => {}
+        // the pid of a process that has been reaped may already be used by another process
+        if self.process.is_none() || self.stopped() {
+            return Ok(());
+        }
+
+        self.signal(Signal::SIGTERM)?;
+
+        // a handler running as PID 1 of a PID namespace ignores SIGTERM unless it handles it
+        if !self.wait_until_stopped(SHUTDOWN_GRACE) {
+            self.signal(Signal::SIGKILL)?;
+
+            if !self.wait_until_stopped(SHUTDOWN_GRACE) {
+                Err(UnixSocketError::StillRunning)?
+            }
         }
 
         Ok(())
     }
 
     fn handle_request(&self, ctx: FunctionContext) -> Result<Vec<u8>, failure::Error> {
-        let json_payload = ctx.to_string()?;
+        let json_payload = serialize(&ctx)?;
         let bytes = json_payload.into_bytes();
 
-        let mut socket = self.make_socket()?;
+        let buf = self
+            .exchange(&bytes)
+            .map_err(|e| socket_error(self.config.name(), e))?;
 
-        socket.connect()?;
+        decode_response(self.config.name(), &buf)
+    }
 
-        socket.poll_write(2500)?;
+    fn handle_streaming(&self, ctx: FunctionContext) -> Result<StreamingResponse, failure::Error> {
+        let json_payload = serialize(&ctx)?;
+        let error = |e| socket_error(self.config.name(), e);
 
-        socket.write(&bytes)?;
+        let mut socket = self.make_socket().map_err(error)?;
 
-        socket.poll_read(2500)?;
+        socket.connect().map_err(error)?;
 
-        let (_, buf) = socket.read_all()?;
+        socket
+            .write_all(json_payload.as_bytes(), SOCKET_TIMEOUT_MS)
+            .map_err(error)?;
 
-        socket.close()?;
+        // the function reads the request until the end of it before it starts the response
+        socket.shutdown_write().map_err(error)?;
 
-        let str_res = String::from_utf8_lossy(&buf).to_string();
-        let json_res = FunctionResponse::from_str(&str_res)?;
-        let bytes_res = json_res.to_bytes()?;
+        FrameReader::read_head(self.config.name(), socket, SOCKET_TIMEOUT_MS)
+    }
+
+    fn connect_websocket(
+        &self,
+        on_message: MessageHandler,
+    ) -> Result<Box<dyn WebSocketConnection>, failure::Error> {
+        let socket = self
+            .make_socket()
+            .map_err(|e| socket_error(self.config.name(), e))?;
+
+        let connection =
+            SocketConnection::open(self.config.name(), socket, on_message, SOCKET_TIMEOUT_MS)?;
+
+        Ok(Box::new(connection))
+    }
+
+    fn stream_request(
+        &self,
+        ctx: FunctionContext,
+    ) -> Result<Box<dyn StreamingRequest>, failure::Error> {
+        let json_payload = serialize(&ctx)?;
 
-        Ok(bytes_res)
+        let socket = self
+            .make_socket()
+            .map_err(|e| socket_error(self.config.name(), e))?;
+
+        let stream = SocketStream::start(
+            self.config.name(),
+            socket,
+            json_payload.as_bytes(),
+            SOCKET_TIMEOUT_MS,
+        )?;
+
+        Ok(Box::new(stream))
+    }
+
+    fn has_exited(&self) -> bool {
+        self.handler_exited()
+    }
+
+    fn restart(&mut self) -> Result<(), failure::Error> {
+        // anything left in the process group is stopped, and the process reaped, before the
+        // handler is started again on the same socket
+        self.shutdown()?;
+
+        self.process = None;
+        self.cgroup = None;
+
+        self.start()?;
+
+        thread::sleep(Duration::from_secs(1));
+
+        Ok(())
+    }
+
+    fn resource_usage(&self) -> Option<ResourceUsage> {
+        self.cgroup.as_ref().map(|cgroup| cgroup.usage())
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.process.as_ref().map(|process| process.id())
     }
 }
 
@@ -137,16 +245,145 @@ impl UnixSocketRuntime {
         let mut command = self.config.cmd();
 
         command.arg(self.sock_name());
+        command.args(self.config.args.iter().map(|arg| arg.value()));
+
+        let sandbox_config = &self.config.sandbox;
+
+        // the process needs to be able to create its socket after dropping privileges
+        if sandbox_config.uid.is_some() || sandbox_config.gid.is_some() {
+            chown(
+                self._tempdir.path(),
+                sandbox_config.uid.map(Uid::from_raw),
+                sandbox_config.gid.map(Gid::from_raw),
+            )
+            .map_err(|e| UnixSocketError::PermissionError(e))?;
+        }
+
+        if let Some(cgroup_config) = &self.config.cgroup {
+            // named after the tempdir, it's unique to this process of the function, so the process
+            // of each worker has its own limits
+            let dir_name = self
+                ._tempdir
+                .path()
+                .file_name()
+                .and_then(|name| name.to_str());
+            let name = format!(
+                "{}-{}",
+                self.config.name(),
+                dir_name.unwrap_or("").trim_start_matches('.')
+            );
+
+            self.cgroup = Cgroup::create(&name, cgroup_config);
+        }
+
+        // the process joins the cgroup before the sandbox drops its privileges
+        if let Some(cgroup) = &self.cgroup {
+            cgroup.apply(&mut command);
+        }
+
+        sandbox::apply(&mut command, sandbox_config);
 
         let process = command
             .spawn()
             .map_err(|e| UnixSocketError::ProcessError(e))?;
 
         self.process = Some(process);
+        self.exited.store(false, Ordering::SeqCst);
 
         Ok(())
     }
 
+    /// Whether the handler has exited, its process is reaped once it has
+    fn handler_exited(&self) -> bool {
+        if self.exited.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        let pid = match &self.process {
+            Some(process) => Pid::from_raw(process.id() as i32),
+            None => return false,
+        };
+
+        // in a PID namespace the spawned process exits right away by design, so it says nothing
+        // about the handler
+        let exited = if self.config.sandbox.namespaces.pid {
+            group_has_exited(pid)
+        } else {
+            match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(..)) | Ok(WaitStatus::Signaled(..)) => true,
+                // already reaped while waiting for its process group
+                Err(nix::Error::Sys(Errno::ECHILD)) => true,
+                _ => false,
+            }
+        };
+
+        if exited {
+            self.exited.store(true, Ordering::SeqCst);
+        }
+
+        exited
+    }
+
+    /// Whether every process of the function has exited, which includes the rest of its process
+    /// group when it leads one
+    fn stopped(&self) -> bool {
+        match &self.process {
+            Some(process) if self.config.sandbox.new_process_group() => {
+                group_has_exited(Pid::from_raw(process.id() as i32))
+            }
+            _ => self.handler_exited(),
+        }
+    }
+
+    fn wait_until_stopped(&self, timeout: Duration) -> bool {
+        let started = Instant::now();
+
+        while !self.stopped() {
+            if started.elapsed() >= timeout {
+                return false;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        true
+    }
+
+    /// Send the signal to the handler, or to every process in its group when it leads one. The
+    /// processes may already have exited
+    fn signal(&self, signal: Signal) -> Result<(), UnixSocketError> {
+        let pid = match &self.process {
+            // a negative pid signals every process in the group
+            Some(process) if self.config.sandbox.new_process_group() => -(process.id() as i32),
+            Some(process) => process.id() as i32,
+            None => return Ok(()),
+        };
+
+        match kill(Pid::from_raw(pid), signal) {
+            Err(nix::Error::Sys(Errno::ESRCH)) => Ok(()),
+            result => result.map_err(|e| UnixSocketError::KillError(e)),
+        }
+    }
+
+    /// Send the payload to the function and read back its response. The writing side of the
+    /// connection is shut down after the payload, so the function can read until the end of it,
+    /// and the function closes the connection once it has written the whole response
+    fn exchange(&self, bytes: &[u8]) -> Result<Vec<u8>, SocketError> {
+        let mut socket = self.make_socket()?;
+
+        socket.connect()?;
+
+        socket.write_all(bytes, SOCKET_TIMEOUT_MS)?;
+
+        socket.shutdown_write()?;
+
+        let buf = socket.read_to_end(SOCKET_TIMEOUT_MS)?;
+
+        socket.close()?;
+
+        Ok(buf)
+    }
+
     pub fn sock_name(&self) -> &PathBuf {
         &self.sock_name
     }
@@ -159,3 +396,107 @@ impl UnixSocketRuntime {
         Socket::new(self.sock_addr.clone())
     }
 }
+
+/// The handler in a PID namespace is forked by the spawned process and stays in its process group,
+/// which is empty once the handler has exited as the kernel kills the rest of the namespace with it
+fn group_has_exited(group: Pid) -> bool {
+    let group = Pid::from_raw(-group.as_raw());
+
+    // the spawned process is reaped, and so is the handler when the pod ends up as its reaper, such
+    // as when the pod runs as PID 1 of a container, zombies would keep the group around
+    while let Ok(status) = waitpid(group, Some(WaitPidFlag::WNOHANG)) {
+        if status == WaitStatus::StillAlive {
+            break;
+        }
+    }
+
+    // without a signal kill only checks whether there's a process to signal
+    match kill(group, None) {
+        Err(nix::Error::Sys(Errno::ESRCH)) => true,
+        _ => false,
+    }
+}
+
+/// Classify an error communicating with the function
+pub(crate) fn socket_error(name: &str, error: SocketError) -> RuntimeError {
+    match error {
+        SocketError::Timeout(timeout) => RuntimeError::Timeout(name.to_string(), timeout as u64),
+        e => RuntimeError::CommunicationError(name.to_string(), e.to_string()),
+    }
+}
+
+/// Encode the context as JSON, within the trace of the invocation
+fn serialize(ctx: &FunctionContext) -> Result<String, failure::Error> {
+    let _span = Span::from_traceparent("serialize", ctx.traceparent.as_ref().map(|s| s.as_str()));
+
+    Ok(ctx.to_string()?)
+}
+
+/// Convert the function's JSON response into the CBOR FunctionResponse expected by the gateway
+pub(crate) fn decode_response(name: &str, buf: &[u8]) -> Result<Vec<u8>, failure::Error> {
+    let str_res = String::from_utf8_lossy(buf).to_string();
+    let json_res = FunctionResponse::from_str(&str_res)
+        .map_err(|e| RuntimeError::MalformedResponse(name.to_string(), e.to_string()))?;
+    let bytes_res = json_res.to_bytes()?;
+
+    Ok(bytes_res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fn_api::frames::{self, FrameType};
+    use fn_api::FunctionRequest;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+
+    /// The test stands in for the handler, which exits right away, by listening on its socket
+    #[test]
+    fn streamed_responses_are_read_until_the_end() {
+        let config = FunctionConfig::new(
+            "stream".to_string(),
+            "GET".into(),
+            "/stream".to_string(),
+            "/bin/true".to_string(),
+            None,
+            "unix_socket".to_string(),
+        );
+        let runtime = UnixSocketRuntime::initialize(&config).unwrap();
+        let listener = UnixListener::bind(runtime.read().sock_name()).unwrap();
+
+        let function = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut request = String::new();
+            stream.read_to_string(&mut request).unwrap();
+
+            let head = frames::encode(FrameType::Head, br#"{"status_code":201}"#);
+            stream.write_all(&head).unwrap();
+            stream
+                .write_all(&frames::encode(FrameType::Body, b"hello "))
+                .unwrap();
+            stream
+                .write_all(&frames::encode(FrameType::Body, b"world"))
+                .unwrap();
+            stream
+                .write_all(&frames::encode(FrameType::End, &[]))
+                .unwrap();
+
+            request
+        });
+
+        let req = FunctionRequest::new("stream", "/bin/true", "/stream", "GET", "");
+        let ctx = FunctionContext::new(req, FunctionResponse::new());
+
+        let res = runtime
+            .read()
+            .handle_streaming(ctx)
+            .unwrap()
+            .collect()
+            .unwrap();
+
+        assert!(function.join().unwrap().contains(r#""path":"/stream""#));
+        assert_eq!(res.status_code, 201);
+        assert_eq!(res.body, b"hello world".to_vec());
+    }
+}
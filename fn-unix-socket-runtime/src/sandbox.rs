@@ -0,0 +1,387 @@
+use fn_core::config::SandboxConfig;
+use nix::mount::{mount, MsFlags};
+use nix::sched::{unshare, CloneFlags};
+use nix::unistd::{fork, setgid, setgroups, setsid, setuid, ForkResult, Gid, Uid};
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+// seccomp and BPF constants, see linux/seccomp.h and linux/filter.h
+const SECCOMP_MODE_FILTER: libc::c_ulong = 2;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_JMP_JGE_K: u16 = 0x35;
+const BPF_JMP_JSET_K: u16 = 0x45;
+const BPF_RET_K: u16 = 0x06;
+/// Offsets into struct seccomp_data, the first argument is read as its low 32 bits
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+const SECCOMP_DATA_ARG0: u32 = 16;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Set in the number of every x32 ABI syscall, which shares the x86_64 audit arch
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// clone3 has the same number on every architecture, it isn't in every version of libc
+const SYS_CLONE3: libc::c_long = 435;
+
+/// Flags of clone that create namespaces, the same as denying unshare
+const CLONE_NAMESPACE_FLAGS: u32 = (libc::CLONE_NEWNS
+    | libc::CLONE_NEWCGROUP
+    | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWIPC
+    | libc::CLONE_NEWUSER
+    | libc::CLONE_NEWPID
+    | libc::CLONE_NEWNET) as u32;
+
+/// Syscalls denied by the seccomp filter, they fail with EPERM. clone and clone3 are handled
+/// separately, see `seccomp_filter`
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_acct,
+    libc::SYS_settimeofday,
+    libc::SYS_clock_settime,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+];
+
+/// Apply the sandbox to the command. Everything happens in the child between fork and exec, so the
+/// closure sticks to syscalls and doesn't allocate; the seccomp filter is built up front.
+pub fn apply(command: &mut Command, config: &SandboxConfig) {
+    let config = config.clone();
+    let filter = if config.seccomp {
+        Some(seccomp_filter())
+    } else {
+        None
+    };
+
+    unsafe {
+        command.pre_exec(move || {
+            if config.new_process_group() {
+                setsid().map_err(to_io)?;
+            }
+
+            set_limits(&config)?;
+
+            let mut flags = CloneFlags::empty();
+
+            if config.namespaces.mount {
+                flags.insert(CloneFlags::CLONE_NEWNS);
+            }
+
+            if config.namespaces.pid {
+                flags.insert(CloneFlags::CLONE_NEWPID);
+            }
+
+            if config.namespaces.network {
+                flags.insert(CloneFlags::CLONE_NEWNET);
+            }
+
+            if !flags.is_empty() {
+                unshare(flags).map_err(to_io)?;
+            }
+
+            if config.namespaces.mount {
+                // keep mounts made inside of the namespace from propagating back to the host
+                mount(
+                    None::<&str>,
+                    "/",
+                    None::<&str>,
+                    MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+                    None::<&str>,
+                )
+                .map_err(to_io)?;
+            }
+
+            if config.namespaces.pid {
+                // only children enter the new PID namespace. The handler is forked so that it runs
+                // as PID 1, the spawned process exits right away and the handler is reached through
+                // the process group
+                match fork().map_err(to_io)? {
+                    ForkResult::Parent { .. } => libc::_exit(0),
+                    ForkResult::Child => {}
+                }
+            }
+
+            if let Some(gid) = config.gid {
+                setgroups(&[Gid::from_raw(gid)]).map_err(to_io)?;
+                setgid(Gid::from_raw(gid)).map_err(to_io)?;
+            }
+
+            if let Some(uid) = config.uid {
+                setuid(Uid::from_raw(uid)).map_err(to_io)?;
+            }
+
+            if let Some(filter) = &filter {
+                install_seccomp(filter)?;
+            }
+
+            Ok(())
+        });
+    }
+}
+
+fn set_limits(config: &SandboxConfig) -> io::Result<()> {
+    let limits = &config.limits;
+
+    let resources = [
+        (libc::RLIMIT_CPU, limits.cpu_seconds),
+        (libc::RLIMIT_AS, limits.address_space),
+        (libc::RLIMIT_NOFILE, limits.open_files),
+        (libc::RLIMIT_NPROC, limits.processes),
+    ];
+
+    for (resource, limit) in resources.iter() {
+        if let Some(limit) = limit {
+            let rlimit = libc::rlimit {
+                rlim_cur: *limit as libc::rlim_t,
+                rlim_max: *limit as libc::rlim_t,
+            };
+
+            if unsafe { libc::setrlimit(*resource, &rlimit) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A deny list filter, syscalls from another architecture are denied outright since the syscall
+/// numbers can't be trusted. clone is only denied when it creates a namespace, and clone3 fails
+/// with ENOSYS since its flags can't be inspected, so that libc falls back to clone.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn seccomp_filter() -> Vec<libc::sock_filter> {
+    let deny = SECCOMP_RET_ERRNO | (libc::EPERM as u32 & 0xffff);
+    let enosys = SECCOMP_RET_ERRNO | (libc::ENOSYS as u32 & 0xffff);
+    let count = DENIED_SYSCALLS.len() as u8;
+
+    // jumps are relative to the next instruction, the instructions after the checks of the
+    // syscall number are: allow, the clone flags check (3), deny and enosys
+    let to_deny = |index: u8| count + 3 - index;
+
+    let mut filter = vec![
+        bpf_stmt(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+        bpf_jump(BPF_JMP_JEQ_K, AUDIT_ARCH, 1, 0),
+        bpf_stmt(BPF_RET_K, deny),
+        bpf_stmt(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+    ];
+
+    // x32 syscalls would get past every check below, they reach the same syscalls with the bit set
+    #[cfg(target_arch = "x86_64")]
+    filter.push(bpf_jump(BPF_JMP_JGE_K, X32_SYSCALL_BIT, count + 6, 0));
+
+    filter.push(bpf_jump(BPF_JMP_JEQ_K, SYS_CLONE3 as u32, count + 6, 0));
+    filter.push(bpf_jump(
+        BPF_JMP_JEQ_K,
+        libc::SYS_clone as u32,
+        count + 1,
+        0,
+    ));
+
+    // each check jumps forward to the deny instruction when it matches
+    for (i, syscall) in DENIED_SYSCALLS.iter().enumerate() {
+        filter.push(bpf_jump(
+            BPF_JMP_JEQ_K,
+            *syscall as u32,
+            to_deny(i as u8),
+            0,
+        ));
+    }
+
+    filter.push(bpf_stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+
+    // clone, denied when its flags create a namespace
+    filter.push(bpf_stmt(BPF_LD_W_ABS, SECCOMP_DATA_ARG0));
+    filter.push(bpf_jump(BPF_JMP_JSET_K, CLONE_NAMESPACE_FLAGS, 1, 0));
+    filter.push(bpf_stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+
+    filter.push(bpf_stmt(BPF_RET_K, deny));
+    filter.push(bpf_stmt(BPF_RET_K, enosys));
+
+    filter
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn seccomp_filter() -> Vec<libc::sock_filter> {
+    vec![]
+}
+
+fn install_seccomp(filter: &[libc::sock_filter]) -> io::Result<()> {
+    if filter.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "seccomp is not supported on this architecture",
+        ));
+    }
+
+    let prog = libc::sock_fprog {
+        len: filter.len() as libc::c_ushort,
+        filter: filter.as_ptr() as *mut libc::sock_filter,
+    };
+
+    unsafe {
+        // required to install a filter without CAP_SYS_ADMIN, also keeps setuid binaries from
+        // regaining privileges
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if libc::prctl(
+            libc::PR_SET_SECCOMP,
+            SECCOMP_MODE_FILTER,
+            &prog as *const libc::sock_fprog,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+fn bpf_stmt(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn bpf_jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+fn to_io(error: nix::Error) -> io::Error {
+    match error {
+        nix::Error::Sys(errno) => io::Error::from_raw_os_error(errno as i32),
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+    }
+}
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod tests {
+    use super::*;
+
+    /// Run the filter against a syscall, only the instructions the filter uses are supported
+    fn run(filter: &[libc::sock_filter], arch: u32, nr: u32, arg0: u32) -> u32 {
+        let mut accumulator = 0;
+        let mut pc = 0;
+
+        loop {
+            let instruction = filter[pc];
+            pc += 1;
+
+            let jump = |matches: bool| {
+                usize::from(if matches {
+                    instruction.jt
+                } else {
+                    instruction.jf
+                })
+            };
+
+            match instruction.code {
+                BPF_LD_W_ABS => {
+                    accumulator = match instruction.k {
+                        SECCOMP_DATA_NR => nr,
+                        SECCOMP_DATA_ARCH => arch,
+                        SECCOMP_DATA_ARG0 => arg0,
+                        offset => panic!("unexpected offset {}", offset),
+                    }
+                }
+                BPF_JMP_JEQ_K => pc += jump(accumulator == instruction.k),
+                BPF_JMP_JGE_K => pc += jump(accumulator >= instruction.k),
+                BPF_JMP_JSET_K => pc += jump(accumulator & instruction.k != 0),
+                BPF_RET_K => return instruction.k,
+                code => panic!("unexpected instruction {}", code),
+            }
+        }
+    }
+
+    fn syscall(nr: libc::c_long, arg0: u32) -> u32 {
+        run(&seccomp_filter(), AUDIT_ARCH, nr as u32, arg0)
+    }
+
+    const EPERM: u32 = SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+    #[test]
+    fn allows_other_syscalls() {
+        assert_eq!(syscall(libc::SYS_read, 0), SECCOMP_RET_ALLOW);
+        assert_eq!(syscall(libc::SYS_write, 0), SECCOMP_RET_ALLOW);
+    }
+
+    #[test]
+    fn denies_every_listed_syscall() {
+        for nr in DENIED_SYSCALLS {
+            assert_eq!(syscall(*nr, 0), EPERM, "syscall {}", nr);
+        }
+    }
+
+    #[test]
+    fn denies_other_architectures() {
+        assert_eq!(
+            run(&seccomp_filter(), 0x4000_0003, libc::SYS_read as u32, 0),
+            EPERM
+        );
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn denies_x32_syscalls() {
+        let filter = seccomp_filter();
+
+        for nr in &[libc::SYS_read, libc::SYS_unshare, libc::SYS_mount] {
+            let x32 = *nr as u32 | X32_SYSCALL_BIT;
+            assert_eq!(run(&filter, AUDIT_ARCH, x32, 0), EPERM, "syscall {}", nr);
+        }
+    }
+
+    #[test]
+    fn denies_clone_with_namespace_flags() {
+        let thread =
+            (libc::CLONE_VM | libc::CLONE_FS | libc::CLONE_FILES | libc::CLONE_THREAD) as u32;
+
+        assert_eq!(syscall(libc::SYS_clone, thread), SECCOMP_RET_ALLOW);
+        assert_eq!(
+            syscall(libc::SYS_clone, libc::SIGCHLD as u32),
+            SECCOMP_RET_ALLOW
+        );
+
+        for flag in &[libc::CLONE_NEWUSER, libc::CLONE_NEWNS, libc::CLONE_NEWNET] {
+            assert_eq!(syscall(libc::SYS_clone, *flag as u32 | thread), EPERM);
+        }
+    }
+
+    #[test]
+    fn clone3_is_unavailable() {
+        assert_eq!(
+            syscall(SYS_CLONE3, 0),
+            SECCOMP_RET_ERRNO | libc::ENOSYS as u32
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use failure::Fail;
 use nix::poll::{poll, PollFd, PollFlags};
-use nix::sys::socket::{getpeername, recv, send, MsgFlags};
+use nix::sys::socket::{getpeername, recv, send, shutdown, MsgFlags, Shutdown};
 use nix::sys::socket::{AddressFamily, SockAddr, SockFlag, SockType};
-use nix::unistd::{close, read, write};
+use nix::unistd::{close, dup, read, write};
 use std::os::unix::io::RawFd;
 
 #[derive(Debug, Fail)]
@@ -46,6 +46,15 @@ pub enum SocketError {
     #[fail(display = "Failed to become ready in time {}", _0)]
     PollTimeout(nix::Error),
 
+    #[fail(display = "Timed out after {}ms waiting for the socket", _0)]
+    Timeout(i32),
+
+    #[fail(display = "The connection was closed before all of the data was read")]
+    UnexpectedEof,
+
+    #[fail(display = "Failed to clone the socket {}", _0)]
+    CloneError(nix::Error),
+
     #[fail(display = "Bad file number (is none?)")]
     BadFileNumber,
 
@@ -88,8 +97,31 @@ impl Socket {
         nix::sys::socket::connect(self.fd(), &self.addr).map_err(|e| SocketError::ConnectError(e))
     }
 
+    /// A second handle to the same connection, so that one thread can read while another writes
+    pub fn try_clone(&self) -> Result<Socket, SocketError> {
+        let fd = dup(self.fd()).map_err(|e| SocketError::CloneError(e))?;
+
+        Ok(Socket {
+            fd,
+            addr: self.addr.clone(),
+        })
+    }
+
+    /// Shut down both directions of the connection, which wakes up any handle blocked on it
+    pub fn shutdown(&self) -> Result<(), SocketError> {
+        shutdown(self.fd(), Shutdown::Both).map_err(|e| SocketError::CloseError(e))
+    }
+
+    /// Shut down the writing side of the connection, the other end reads the end of the data
+    pub fn shutdown_write(&self) -> Result<(), SocketError> {
+        shutdown(self.fd(), Shutdown::Write).map_err(|e| SocketError::CloseError(e))
+    }
+
+    /// Close the socket now, rather than when it's dropped
     pub fn close(&mut self) -> Result<(), SocketError> {
-        close(self.fd()).map_err(|e| SocketError::CloseError(e))
+        let fd = std::mem::replace(&mut self.fd, -1);
+
+        close(fd).map_err(|e| SocketError::CloseError(e))
     }
 
     pub fn fd(&self) -> RawFd {
@@ -104,44 +136,141 @@ impl Socket {
         write(self.fd(), buf).map_err(|e| SocketError::WriteError(e))
     }
 
-    pub fn recv<'a>(&self, buf: &'a mut [u8]) -> Result<(usize, &'a [u8]), SocketError> {
-        let bytes_read =
-            recv(self.fd(), buf, MsgFlags::empty()).map_err(|e| SocketError::RecvError(e))?;
-        Ok((bytes_read, buf))
+    /// Write the whole buffer, waiting for the socket to become writable between partial writes
+    pub fn write_all(&mut self, mut buf: &[u8], timeout: i32) -> Result<(), SocketError> {
+        while !buf.is_empty() {
+            self.poll_write(timeout)?;
+
+            let written = self.write(buf)?;
+            buf = &buf[written..];
+        }
+
+        Ok(())
     }
 
-    pub fn read<'a>(&self, buf: &'a mut [u8]) -> Result<(usize, &'a [u8]), SocketError> {
-        let bytes_read = read(self.fd(), buf).map_err(|e| SocketError::ReadError(e))?;
-        Ok((bytes_read, buf))
+    /// Fill the whole buffer, waiting for the socket to become readable between partial reads
+    pub fn read_exact(&mut self, buf: &mut [u8], timeout: i32) -> Result<(), SocketError> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            self.poll_read(timeout)?;
+
+            let (bytes_read, _) = self.read(&mut buf[filled..])?;
+
+            if bytes_read == 0 {
+                return Err(SocketError::UnexpectedEof);
+            }
+
+            filled += bytes_read;
+        }
+
+        Ok(())
     }
 
-    pub fn read_all(&self) -> Result<(usize, Vec<u8>), SocketError> {
-        let buffer_size = 128;
-        let mut buf: Vec<u8> = vec![0; 128];
+    /// Read until the other end closes the connection
+    pub fn read_to_end(&mut self, timeout: i32) -> Result<Vec<u8>, SocketError> {
+        let mut buf: Vec<u8> = vec![0; 4096];
         let mut output: Vec<u8> = vec![];
-        let mut total_bytes = 0;
 
-        while {
+        loop {
+            self.poll_read(timeout)?;
+
             let (bytes_read, buf) = self.read(&mut buf)?;
+
+            if bytes_read == 0 {
+                return Ok(output);
+            }
+
             output.extend_from_slice(&buf[..bytes_read]);
-            total_bytes += bytes_read;
-            bytes_read > buffer_size
-        } {}
+        }
+    }
 
-        Ok((total_bytes, output))
+    pub fn recv<'a>(&self, buf: &'a mut [u8]) -> Result<(usize, &'a [u8]), SocketError> {
+        let bytes_read =
+            recv(self.fd(), buf, MsgFlags::empty()).map_err(|e| SocketError::RecvError(e))?;
+        Ok((bytes_read, buf))
+    }
+
+    pub fn read<'a>(&self, buf: &'a mut [u8]) -> Result<(usize, &'a [u8]), SocketError> {
+        let bytes_read = read(self.fd(), buf).map_err(|e| SocketError::ReadError(e))?;
+        Ok((bytes_read, buf))
     }
 
     pub fn poll_write(&mut self, timeout: i32) -> Result<i32, SocketError> {
         let poll_fd = PollFd::new(self.fd(), PollFlags::POLLOUT);
-        poll(&mut [poll_fd], timeout).map_err(|e| SocketError::PollTimeout(e))
+        Socket::poll_result(poll(&mut [poll_fd], timeout), timeout)
     }
 
     pub fn poll_read(&mut self, timeout: i32) -> Result<i32, SocketError> {
         let poll_fd = PollFd::new(self.fd(), PollFlags::POLLIN);
-        poll(&mut [poll_fd], timeout).map_err(|e| SocketError::PollTimeout(e))
+        Socket::poll_result(poll(&mut [poll_fd], timeout), timeout)
+    }
+
+    /// poll returns 0 when none of the fds became ready before the timeout
+    fn poll_result(result: nix::Result<i32>, timeout: i32) -> Result<i32, SocketError> {
+        match result {
+            Ok(0) => Err(SocketError::Timeout(timeout)),
+            Ok(ready) => Ok(ready),
+            Err(e) => Err(SocketError::PollTimeout(e)),
+        }
     }
 
     pub fn get_peer_name(&self) -> Result<SockAddr, SocketError> {
         getpeername(self.fd).map_err(|e| SocketError::StatusError(e))
     }
 }
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        // the socket is closed when it's dropped on an error path or partway through a stream
+        if self.fd >= 0 {
+            let _ = close(self.fd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    const TIMEOUT_MS: i32 = 5_000;
+
+    /// Payloads larger than the buffers of the socket, so they take many reads and writes
+    #[test]
+    fn large_payloads_are_sent_and_read_whole() {
+        let path = std::env::temp_dir().join(format!("fn-socket-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let request: Vec<u8> = (0..4_000_000).map(|i| (i % 251) as u8).collect();
+        let response: Vec<u8> = (0..3_000_000).map(|i| (i % 241) as u8).collect();
+
+        let expected = response.clone();
+
+        // echoes the length of the request it read until the end, then the response
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut received = vec![];
+            stream.read_to_end(&mut received).unwrap();
+            stream.write_all(&response).unwrap();
+
+            received
+        });
+
+        let mut socket = Socket::new(SockAddr::new_unix(&path).unwrap()).unwrap();
+        socket.connect().unwrap();
+        socket.write_all(&request, TIMEOUT_MS).unwrap();
+        socket.shutdown_write().unwrap();
+
+        let received_response = socket.read_to_end(TIMEOUT_MS).unwrap();
+
+        assert_eq!(server.join().unwrap(), request);
+        assert_eq!(received_response, expected);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
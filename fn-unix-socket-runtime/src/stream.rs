@@ -0,0 +1,190 @@
+use crate::runtime::{decode_response, socket_error};
+use crate::socket::Socket;
+use fn_api::frames::{self, FrameType};
+use fn_core::runtime::{RuntimeError, StreamingRequest, StreamingResponse};
+
+/// Largest frame accepted from a function, larger frames are rejected rather than allocated
+const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+/// How long to wait for each frame once a streamed response has started, a function that
+/// generates its response as it goes may pause between chunks
+const STREAM_IDLE_TIMEOUT_MS: i32 = 30_000;
+
+/// A request to a unix socket function whose body is written in `Body` frames as it arrives
+pub struct SocketStream {
+    name: String,
+    socket: Socket,
+    timeout: i32,
+}
+
+impl SocketStream {
+    /// Connect to the function and send the `Request` frame
+    pub fn start(
+        name: &str,
+        mut socket: Socket,
+        ctx: &[u8],
+        timeout: i32,
+    ) -> Result<SocketStream, failure::Error> {
+        let error = |e| socket_error(name, e);
+
+        socket.connect().map_err(error)?;
+
+        socket
+            .write_all(&frames::encode(FrameType::Request, ctx), timeout)
+            .map_err(error)?;
+
+        Ok(SocketStream {
+            name: name.to_string(),
+            socket,
+            timeout,
+        })
+    }
+
+    fn end(&mut self) -> Result<(), failure::Error> {
+        self.socket
+            .write_all(&frames::encode(FrameType::End, &[]), self.timeout)
+            .map_err(|e| socket_error(&self.name, e))?;
+
+        Ok(())
+    }
+}
+
+impl StreamingRequest for SocketStream {
+    fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), failure::Error> {
+        // an empty Body frame would be indistinguishable from a missing chunk, so it's skipped
+        if chunk.is_empty() {
+            return Ok(());
+        }
+
+        self.socket
+            .write_all(&frames::encode(FrameType::Body, chunk), self.timeout)
+            .map_err(|e| socket_error(&self.name, e))?;
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Vec<u8>, failure::Error> {
+        self.end()?;
+
+        let buf = self
+            .socket
+            .read_to_end(self.timeout)
+            .map_err(|e| socket_error(&self.name, e))?;
+
+        decode_response(&self.name, &buf)
+    }
+
+    fn finish_streaming(mut self: Box<Self>) -> Result<StreamingResponse, failure::Error> {
+        self.end()?;
+
+        let stream = *self;
+
+        FrameReader::read_head(&stream.name, stream.socket, stream.timeout)
+    }
+}
+
+/// Read the next frame from the function, a negative timeout waits indefinitely
+pub(crate) fn read_frame(
+    name: &str,
+    socket: &mut Socket,
+    timeout: i32,
+) -> Result<(FrameType, Vec<u8>), failure::Error> {
+    let malformed = |reason: String| RuntimeError::MalformedResponse(name.to_string(), reason);
+    let mut header = [0u8; frames::HEADER_LEN];
+
+    socket
+        .read_exact(&mut header, timeout)
+        .map_err(|e| socket_error(name, e))?;
+
+    let (frame_type, len) = match frames::decode_header(header) {
+        Some(decoded) => decoded,
+        None => return Err(malformed(format!("unknown frame type {}", header[0])))?,
+    };
+
+    if len > MAX_FRAME_LEN {
+        return Err(malformed(format!(
+            "frame of {} bytes is larger than the limit of {} bytes",
+            len, MAX_FRAME_LEN
+        )))?;
+    }
+
+    let mut payload = vec![0; len as usize];
+
+    socket
+        .read_exact(&mut payload, timeout)
+        .map_err(|e| socket_error(name, e))?;
+
+    Ok((frame_type, payload))
+}
+
+/// Reads the frames of a streamed response, the body is read by iterating over it
+pub struct FrameReader {
+    name: String,
+    socket: Socket,
+    done: bool,
+}
+
+impl FrameReader {
+    /// Wait for the `Head` frame of the response, the rest of the frames are read by the body
+    pub fn read_head(
+        name: &str,
+        socket: Socket,
+        timeout: i32,
+    ) -> Result<StreamingResponse, failure::Error> {
+        let mut reader = FrameReader {
+            name: name.to_string(),
+            socket,
+            done: false,
+        };
+
+        let head = match reader.next_frame(timeout)? {
+            (FrameType::Head, payload) => decode_response(name, &payload)?,
+            (frame_type, _) => {
+                return Err(reader.malformed(format!(
+                    "expected a Head frame, found a {:?} frame",
+                    frame_type
+                )))?;
+            }
+        };
+
+        Ok(StreamingResponse {
+            head,
+            body: Box::new(reader),
+        })
+    }
+
+    fn next_frame(&mut self, timeout: i32) -> Result<(FrameType, Vec<u8>), failure::Error> {
+        read_frame(&self.name, &mut self.socket, timeout)
+    }
+
+    fn malformed(&self, reason: String) -> RuntimeError {
+        RuntimeError::MalformedResponse(self.name.clone(), reason)
+    }
+}
+
+impl Iterator for FrameReader {
+    type Item = Result<Vec<u8>, failure::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.next_frame(STREAM_IDLE_TIMEOUT_MS) {
+                // an empty chunk would end a chunked response early
+                Ok((FrameType::Body, ref payload)) if payload.is_empty() => continue,
+                Ok((FrameType::Body, payload)) => return Some(Ok(payload)),
+                Ok((FrameType::End, _)) => self.done = true,
+                Ok((frame_type, _)) => {
+                    self.done = true;
+
+                    let reason = format!("unexpected {:?} frame in the body", frame_type);
+                    return Some(Err(self.malformed(reason).into()));
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,100 @@
+use crate::runtime::socket_error;
+use crate::socket::Socket;
+use crate::stream::read_frame;
+use fn_api::frames::{self, FrameType};
+use fn_api::websocket::WebSocketContext;
+use fn_api::ConvertFunction;
+use fn_core::runtime::{MessageHandler, WebSocketConnection, WebSocketMessage};
+use log::warn;
+use std::thread;
+
+/// A connection to the function that stays open for the lifetime of a websocket. Events are
+/// written as `Event` frames, and the messages pushed by the function are read on their own thread.
+pub struct SocketConnection {
+    name: String,
+    socket: Socket,
+    timeout: i32,
+}
+
+impl SocketConnection {
+    pub fn open(
+        name: &str,
+        socket: Socket,
+        on_message: MessageHandler,
+        timeout: i32,
+    ) -> Result<SocketConnection, failure::Error> {
+        let error = |e| socket_error(name, e);
+
+        socket.connect().map_err(error)?;
+
+        let reader = socket.try_clone().map_err(error)?;
+        let reader_name = name.to_string();
+
+        thread::spawn(move || read_messages(&reader_name, reader, on_message));
+
+        Ok(SocketConnection {
+            name: name.to_string(),
+            socket,
+            timeout,
+        })
+    }
+}
+
+impl WebSocketConnection for SocketConnection {
+    fn send(&mut self, event: WebSocketContext) -> Result<(), failure::Error> {
+        let json_payload = event.to_string()?;
+
+        self.socket
+            .write_all(
+                &frames::encode(FrameType::Event, json_payload.as_bytes()),
+                self.timeout,
+            )
+            .map_err(|e| socket_error(&self.name, e))?;
+
+        Ok(())
+    }
+}
+
+impl Drop for SocketConnection {
+    fn drop(&mut self) {
+        // wakes up the reader, which is waiting on its own handle to the connection
+        let _ = self.socket.shutdown();
+    }
+}
+
+/// Pass each message pushed by the function to the handler until the connection is closed, by
+/// either side, which closes the websocket
+fn read_messages(name: &str, mut socket: Socket, on_message: MessageHandler) {
+    loop {
+        let message = match read_frame(name, &mut socket, -1) {
+            Ok((FrameType::Text, payload)) => match String::from_utf8(payload) {
+                Ok(text) => WebSocketMessage::Text(text),
+                Err(_) => {
+                    warn!("function {} sent a text message that isn't UTF-8", name);
+                    WebSocketMessage::Close
+                }
+            },
+            Ok((FrameType::Binary, payload)) => WebSocketMessage::Binary(payload),
+            Ok((FrameType::Close, _)) => WebSocketMessage::Close,
+            Ok((frame_type, _)) => {
+                warn!(
+                    "function {} sent an unexpected {:?} frame",
+                    name, frame_type
+                );
+                WebSocketMessage::Close
+            }
+            Err(_) => WebSocketMessage::Close,
+        };
+
+        let closed = match message {
+            WebSocketMessage::Close => true,
+            _ => false,
+        };
+
+        on_message(message);
+
+        if closed {
+            return;
+        }
+    }
+}
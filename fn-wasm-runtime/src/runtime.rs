@@ -1,12 +1,21 @@
+use fn_api::websocket::WebSocketContext;
 use fn_api::{ConvertFunction, FunctionContext, WasmResponse};
 use fn_core::config::FunctionConfig;
-use fn_core::runtime::RuntimeManager;
+use fn_core::metrics;
+use fn_core::runtime::{
+    MessageHandler, RuntimeError, RuntimeManager, WebSocketConnection, WebSocketMessage,
+};
+use fn_core::secrets::ConfigValue;
+use fn_core::tracing::Span;
 
 use parking_lot::RwLock;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::c_void;
 use std::fs::File;
 use std::io::Read;
 use std::sync::Arc;
-use wasmer_runtime::{func, imports, validate, Ctx};
+use wasmer_runtime::{func, imports, validate, Ctx, Instance};
 
 use failure::Fail;
 
@@ -26,8 +35,8 @@ pub enum WasmRuntimeError {
     #[fail(display = "Failed to compile wasm {}", _0)]
     CompileWasmError(wasmer_runtime::error::CompileError),
 
-    #[fail(display = "Failed to instantiate module")]
-    InstantiationError,
+    #[fail(display = "Failed to instantiate module: {}", _0)]
+    InstantiationError(String),
 
     #[fail(display = "Failed to resolve function in module {}", _0)]
     ResolveError(wasmer_runtime::error::ResolveError),
@@ -94,28 +103,17 @@ impl RuntimeManager for WasmRuntime {
     }
 
     fn handle_request(&self, ctx: FunctionContext) -> Result<Vec<u8>, failure::Error> {
+        let traceparent = ctx.traceparent.as_ref().map(|s| s.as_str());
+        let span = Span::from_traceparent("serialize", traceparent);
         let data = ctx.to_bytes()?;
-        let import_object = imports! {
-            "env" => {
-                "print" => func!(print),
-            },
-        };
-
-        let mut instance = self.module.instantiate(&import_object).map_err(|e| {
-            dbg!(e);
-            WasmRuntimeError::InstantiationError
-        })?;
-
-        let ctx = instance.context_mut();
+        drop(span);
 
-        let memory = ctx.memory(0);
+        let host = HostContext {
+            env: &self.config.env,
+            on_message: None,
+        };
 
-        for (byte, cell) in data
-            .iter()
-            .zip(memory.view::<u8>()[1 as usize..=data.len() as usize].iter())
-        {
-            cell.set(byte.to_owned());
-        }
+        let instance = instantiate(&self.module, &data, &host)?;
 
         let handle_request = instance
             .func::<(i32, i32), i32>("handle_request")
@@ -124,38 +122,152 @@ impl RuntimeManager for WasmRuntime {
         // returns the ptr to the WasmResponse bytes
         let raw_ptr = handle_request
             .call(1i32, data.len() as i32)
-            .map_err(|e| match e {
-                wasmer_runtime::error::RuntimeError::Error { data } => {
-                    dbg!(data);
-                    WasmRuntimeError::RuntimeError("?".to_string())
-                }
-                wasmer_runtime::error::RuntimeError::Trap { msg } => {
-                    WasmRuntimeError::RuntimeTrap(msg.to_string())
-                }
-            })?;
+            .map_err(|e| call_error(&self.config, e))?;
+
+        let malformed = |reason: String| {
+            RuntimeError::MalformedResponse(self.config.name().to_string(), reason)
+        };
 
         // load the WasmResponse
-        let end_location: usize = raw_ptr as usize + 16usize;
         let ctx = instance.context();
-        let view = &ctx.memory(0).view();
-        let bytes = view[raw_ptr as usize..end_location]
-            .iter()
-            .map(|cell| cell.get())
-            .collect::<Vec<u8>>();
+        let view = &ctx.memory(0).view::<u8>();
 
-        let wasm_response = WasmResponse::from_slice(&bytes)?;
+        let bytes = read_memory(view, raw_ptr as usize, 16usize)
+            .ok_or_else(|| malformed("WasmResponse is outside of memory".to_string()))?;
+
+        let wasm_response =
+            WasmResponse::from_slice(&bytes).map_err(|e| malformed(e.to_string()))?;
 
         // using the WasmResponse load the FunctionResponse from memory
-        let end_location: usize = wasm_response.ptr as usize + wasm_response.len as usize;
-        let ctx = instance.context();
-        let view = &ctx.memory(0).view();
-        let bytes = view[wasm_response.ptr as usize..end_location]
-            .iter()
-            .map(|cell| cell.get())
-            .collect::<Vec<u8>>();
+        let bytes = read_memory(view, wasm_response.ptr as usize, wasm_response.len as usize)
+            .ok_or_else(|| malformed("FunctionResponse is outside of memory".to_string()))?;
 
         Ok(bytes)
     }
+
+    fn connect_websocket(
+        &self,
+        on_message: MessageHandler,
+    ) -> Result<Box<dyn WebSocketConnection>, failure::Error> {
+        Ok(Box::new(WasmConnection {
+            config: self.config.clone(),
+            module: self.module.clone(),
+            on_message,
+        }))
+    }
+}
+
+/// A websocket backed by a wasm function. Each event is handled by a new instance of the module,
+/// which calls `handle_websocket` with the CBOR WebSocketContext. While handling the event the
+/// function can push messages to the client with the `ws_send` and `ws_close` host functions.
+pub struct WasmConnection {
+    config: FunctionConfig,
+    module: wasmer_runtime::Module,
+    on_message: MessageHandler,
+}
+
+impl WebSocketConnection for WasmConnection {
+    fn send(&mut self, event: WebSocketContext) -> Result<(), failure::Error> {
+        let data = event.to_bytes()?;
+
+        let host = HostContext {
+            env: &self.config.env,
+            on_message: Some(&self.on_message),
+        };
+
+        let instance = instantiate(&self.module, &data, &host)?;
+
+        let handle_websocket = instance
+            .func::<(i32, i32), ()>("handle_websocket")
+            .map_err(|e| WasmRuntimeError::ResolveError(e))?;
+
+        handle_websocket
+            .call(1i32, data.len() as i32)
+            .map_err(|e| call_error(&self.config, e))?;
+
+        Ok(())
+    }
+}
+
+/// Data the host functions read through the context's data pointer
+struct HostContext<'a> {
+    /// Environment of the function, read by `env_var`
+    env: &'a HashMap<String, ConfigValue>,
+    /// Receives the messages pushed with `ws_send` and `ws_close`, only set while handling an event
+    /// of a websocket
+    on_message: Option<&'a MessageHandler>,
+}
+
+/// Create an instance of the module and copy the data into its memory, starting at 1
+fn instantiate(
+    module: &wasmer_runtime::Module,
+    data: &[u8],
+    host: &HostContext,
+) -> Result<Instance, failure::Error> {
+    let import_object = imports! {
+        "env" => {
+            "print" => func!(print),
+            "env_var" => func!(env_var),
+            "ws_send" => func!(ws_send),
+            "ws_close" => func!(ws_close),
+        },
+    };
+
+    let mut instance = module
+        .instantiate(&import_object)
+        .map_err(|e| WasmRuntimeError::InstantiationError(e.to_string()))?;
+
+    let ctx = instance.context_mut();
+
+    // the pointer is only valid while the host context is borrowed, which outlives every call to
+    // the instance
+    ctx.data = host as *const HostContext as *mut c_void;
+
+    let memory = ctx.memory(0);
+
+    for (byte, cell) in data
+        .iter()
+        .zip(memory.view::<u8>()[1 as usize..=data.len() as usize].iter())
+    {
+        cell.set(byte.to_owned());
+    }
+
+    Ok(instance)
+}
+
+/// Classify an error from calling into the module, a trap means that the function crashed
+fn call_error(config: &FunctionConfig, error: wasmer_runtime::error::RuntimeError) -> RuntimeError {
+    let error = match error {
+        // the data is what a host function failed with, such as the payload of a panic
+        wasmer_runtime::error::RuntimeError::Error { data } => {
+            let message = match data.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => data
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .unwrap_or_else(|| "unknown error".to_string()),
+            };
+
+            WasmRuntimeError::RuntimeError(message)
+        }
+        wasmer_runtime::error::RuntimeError::Trap { msg } => {
+            metrics::WASM_TRAPS
+                .with_label_values(&[config.name(), &config.route])
+                .inc();
+
+            WasmRuntimeError::RuntimeTrap(msg.to_string())
+        }
+    };
+
+    RuntimeError::Crashed(config.name().to_string(), error.to_string())
+}
+
+/// Copy bytes out of the memory, none if the range is outside of it
+fn read_memory(view: &[Cell<u8>], ptr: usize, len: usize) -> Option<Vec<u8>> {
+    let end = ptr.checked_add(len)?;
+
+    view.get(ptr..end)
+        .map(|cells| cells.iter().map(|cell| cell.get()).collect())
 }
 
 fn load_str(ctx: &mut Ctx, ptr: u32, len: u32) -> String {
@@ -169,6 +281,90 @@ fn load_str(ctx: &mut Ctx, ptr: u32, len: u32) -> String {
     std::string::String::from_utf8_lossy(str_slice).into_owned()
 }
 
+/// Passed to WebAssembly functions, copies the value of an environment variable from the function's
+/// config into the buffer at `buf_ptr`. Returns the length of the value, or -1 if it isn't set or a
+/// pointer is outside of the module's memory. If the value doesn't fit in the buffer nothing is
+/// copied, and the function should call again with a buffer of at least the returned length.
+fn env_var(ctx: &mut Ctx, key_ptr: u32, key_len: u32, buf_ptr: u32, buf_len: u32) -> i32 {
+    if ctx.data.is_null() {
+        return -1;
+    }
+
+    // the pointers come from the module, so they're checked rather than trusted
+    let key = {
+        let view = ctx.memory(0).view::<u8>();
+
+        match read_memory(&view, key_ptr as usize, key_len as usize) {
+            Some(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            None => return -1,
+        }
+    };
+    let host = unsafe { &*(ctx.data as *const HostContext) };
+
+    let value = match host.env.get(&key) {
+        Some(value) => value.value().as_bytes(),
+        None => return -1,
+    };
+
+    if value.len() <= buf_len as usize {
+        let memory = ctx.memory(0);
+        let view = memory.view::<u8>();
+        let start = buf_ptr as usize;
+
+        let cells = match start
+            .checked_add(value.len())
+            .and_then(|end| view.get(start..end))
+        {
+            Some(cells) => cells,
+            None => return -1,
+        };
+
+        for (byte, cell) in value.iter().zip(cells.iter()) {
+            cell.set(byte.to_owned());
+        }
+    }
+
+    value.len() as i32
+}
+
+/// Passed to WebAssembly functions, pushes the message at `ptr` to the client of the websocket,
+/// as binary when `binary` is 1 and otherwise as text. Returns -1 when not handling a websocket event
+fn ws_send(ctx: &mut Ctx, ptr: u32, len: u32, binary: u32) -> i32 {
+    let message = {
+        let view = ctx.memory(0).view::<u8>();
+
+        match read_memory(&view, ptr as usize, len as usize) {
+            Some(bytes) if binary == 1 => WebSocketMessage::Binary(bytes),
+            Some(bytes) => WebSocketMessage::Text(String::from_utf8_lossy(&bytes).into_owned()),
+            None => return -1,
+        }
+    };
+
+    push_message(ctx, message)
+}
+
+/// Passed to WebAssembly functions, closes the websocket. Returns -1 when not handling a websocket
+/// event
+fn ws_close(ctx: &mut Ctx) -> i32 {
+    push_message(ctx, WebSocketMessage::Close)
+}
+
+fn push_message(ctx: &mut Ctx, message: WebSocketMessage) -> i32 {
+    if ctx.data.is_null() {
+        return -1;
+    }
+
+    let host = unsafe { &*(ctx.data as *const HostContext) };
+
+    match host.on_message {
+        Some(on_message) => {
+            on_message(message);
+            0
+        }
+        None => -1,
+    }
+}
+
 /// passed to WebAssembly functions, to be used for debugging
 fn print(ctx: &mut Ctx, ptr: u32, len: u32) {
     dbg!(format!("println ptr {} len {}", ptr, len));
@@ -0,0 +1,153 @@
+use actix_web::web;
+use clap::ArgMatches;
+use fn_core::config::{Config, RouteType};
+use fn_core::runtime::evict;
+use fn_core::secrets::{self, EncryptedFileProvider, SecretProvider};
+use fn_core::state::State;
+use std::fs;
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::Errors;
+
+/// Print every problem with the config, an error if there are any
+pub fn validate(config: &Config) -> Result<(), Errors> {
+    if let Err(errors) = config.validate() {
+        eprintln!("Found {} problem(s) in the config:", errors.len());
+
+        for error in &errors {
+            eprintln!("  {}", error);
+        }
+
+        return Err(Errors::InvalidConfig("Config failed validation"));
+    }
+
+    Ok(())
+}
+
+/// Print the route table, one line for each function
+pub fn routes(config: &Config) {
+    let rows: Vec<[String; 5]> = config
+        .functions_iter()
+        .map(|func| {
+            [
+                func.method.list().join(","),
+                func.route.clone(),
+                func.name().to_string(),
+                func.runtime.clone(),
+                match func.route_type {
+                    RouteType::Http => "http".to_string(),
+                    RouteType::WebSocket => "websocket".to_string(),
+                },
+            ]
+        })
+        .collect();
+
+    let header = [
+        "METHODS".to_string(),
+        "ROUTE".to_string(),
+        "FUNCTION".to_string(),
+        "RUNTIME".to_string(),
+        "TYPE".to_string(),
+    ];
+
+    let mut widths = [0usize; 5];
+
+    for row in rows.iter().chain(std::iter::once(&header)) {
+        for (width, column) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(column, width)| format!("{:<width$}", column, width = width))
+            .collect();
+
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Run a function once, without the HTTP server. The status and headers of the response are
+/// written to stderr and its body to stdout
+pub fn invoke(config: &Config, args: &ArgMatches) -> Result<(), Errors> {
+    let name = args.value_of("function").unwrap_or_default();
+
+    let func = config
+        .functions_iter()
+        .find(|func| func.name() == name)
+        .ok_or_else(|| Errors::UnknownFunction(name.to_string()))?;
+
+    let method = args.value_of("method").unwrap_or("GET").to_uppercase();
+
+    // the route is only a path when it doesn't have any parameters
+    let path = match args.value_of("path") {
+        Some(path) => path,
+        None if func.route.contains('{') || func.route.ends_with('*') => {
+            Err(Errors::PathRequired(func.route.clone()))?
+        }
+        None => &func.route,
+    };
+    let body = args.value_of("body").unwrap_or("");
+
+    let secrets: Arc<dyn SecretProvider> = Arc::from(
+        config
+            .secrets()
+            .provider()
+            .map_err(|e| Errors::SecretsError(e))?,
+    );
+    let state = web::Data::new(State::new(secrets));
+
+    let func_res = fn_gateway::invoke(state.clone(), func, &method, path, body.as_bytes());
+
+    // stops the function's process
+    if let Err(e) = evict(&state, func) {
+        eprintln!("Failed to shut down function {}: {}", func.name(), e);
+    }
+
+    let func_res = func_res.map_err(|e| Errors::InvokeError(e))?;
+
+    eprintln!("{}", func_res.status_code);
+
+    for (name, value) in func_res.headers.iter() {
+        eprintln!("{}: {}", name, value);
+    }
+
+    for cookie in func_res.cookies.iter() {
+        eprintln!("set-cookie: {}", cookie);
+    }
+
+    std::io::stdout()
+        .write_all(&func_res.body)
+        .map_err(|e| Errors::WebError(e))?;
+
+    Ok(())
+}
+
+/// Print a key for the encrypted_file secrets provider
+pub fn generate_key() -> Result<(), Errors> {
+    let key = secrets::generate_key().map_err(|e| Errors::SecretsError(e))?;
+
+    println!("{}", key);
+    Ok(())
+}
+
+/// Encrypt a YAML file of secrets into the format read by the encrypted_file provider
+pub fn encrypt_secrets(args: &ArgMatches) -> Result<(), Errors> {
+    let input = args.value_of("input").unwrap_or_default();
+    let key_file = args.value_of("key-file").unwrap_or_default();
+    let output = args.value_of("output").unwrap_or_default();
+
+    let key = secrets::read_key(key_file).map_err(|e| Errors::SecretsError(e))?;
+    let plaintext = fs::read(input).map_err(|e| Errors::FileError(input.to_string(), e))?;
+
+    let encrypted =
+        EncryptedFileProvider::encrypt(&key, &plaintext).map_err(|e| Errors::SecretsError(e))?;
+
+    fs::write(output, encrypted).map_err(|e| Errors::FileError(output.to_string(), e))?;
+
+    eprintln!("Encrypted the secrets of {} into {}", input, output);
+    Ok(())
+}
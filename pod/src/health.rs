@@ -1,8 +1,48 @@
 use actix_web::http::StatusCode;
-use actix_web::{HttpRequest, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
+use fn_core::config::Config;
+use fn_core::state::{FunctionStatus, WorkerStates};
+use serde_json::json;
 
-/// This will eventually be expanded to give meaningful information, right now it is just an indicator
-/// if the server is accepting requests. Does not reflect the health of the endpoints
-pub fn handle(_req: HttpRequest) -> HttpResponse {
+/// Liveness, the server is accepting requests. Doesn't reflect the health of the functions, a
+/// crashed function is restarted rather than the pod
+pub fn live(_req: HttpRequest) -> HttpResponse {
     HttpResponse::build(StatusCode::OK).body("ok")
 }
+
+/// Readiness, fails until every worker has started and every function marked `warm` has initialized
+/// in all of them, and whenever one of them has crashed or is restarting. Reports the status of
+/// every function
+pub fn ready(workers: web::Data<WorkerStates>, config: web::Data<Config>) -> HttpResponse {
+    let mut ready = workers.started();
+
+    let functions: Vec<serde_json::Value> = config
+        .functions_iter()
+        .map(|func| {
+            let status = workers.status(func.name());
+
+            if func.warm && status != FunctionStatus::Ready {
+                ready = false;
+            }
+
+            json!({
+                "name": func.name(),
+                "route": func.route,
+                "runtime": func.runtime,
+                "warm": func.warm,
+                "status": status,
+            })
+        })
+        .collect();
+
+    let status_code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    HttpResponse::build(status_code).json(json!({
+        "ready": ready,
+        "functions": functions,
+    }))
+}
@@ -0,0 +1,310 @@
+use failure::Fail;
+use fn_core::config::{Bind, ListenerConfig};
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::sys::socket::{getsockname, SockAddr};
+use std::env;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process;
+
+/// The first file descriptor passed by systemd, after stdin, stdout and stderr
+const LISTEN_FDS_START: RawFd = 3;
+
+#[derive(Debug, Fail)]
+pub enum ListenError {
+    #[fail(display = "Failed to listen on {}: {}", _0, _1)]
+    BindError(String, std::io::Error),
+
+    #[fail(display = "No sockets named \"{}\" were passed by systemd", _0)]
+    NoNamedSockets(String),
+
+    #[fail(display = "No sockets were passed by systemd")]
+    NoSockets,
+
+    #[fail(display = "Socket {} passed by systemd is invalid: {}", _0, _1)]
+    InvalidSocket(RawFd, nix::Error),
+
+    #[fail(
+        display = "Socket {} passed by systemd is not a TCP or unix stream socket",
+        _0
+    )]
+    UnsupportedSocket(RawFd),
+}
+
+/// A socket the server accepts connections from
+pub enum Socket {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Take the sockets passed by systemd socket activation, with the name of each. The variables
+/// describing them are removed so that the processes of the functions don't inherit them
+pub fn systemd_sockets() -> Result<Vec<(String, Socket)>, ListenError> {
+    let pid = env::var("LISTEN_PID").ok();
+    let count = env::var("LISTEN_FDS").ok();
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    // the sockets were meant for another process when the pid doesn't match
+    if pid.and_then(|pid| pid.parse::<u32>().ok()) != Some(process::id()) {
+        return Ok(vec![]);
+    }
+
+    let count = count
+        .and_then(|count| count.parse::<RawFd>().ok())
+        .unwrap_or(0);
+    let names: Vec<&str> = names.split(':').collect();
+
+    let mut sockets = vec![];
+
+    for (index, fd) in (LISTEN_FDS_START..LISTEN_FDS_START + count).enumerate() {
+        fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))
+            .map_err(|e| ListenError::InvalidSocket(fd, e))?;
+
+        // the family alone would let a UDP or SEQPACKET socket through as a listener
+        if socket_type(fd).map_err(|e| ListenError::InvalidSocket(fd, e))? != libc::SOCK_STREAM {
+            Err(ListenError::UnsupportedSocket(fd))?;
+        }
+
+        let socket = match getsockname(fd).map_err(|e| ListenError::InvalidSocket(fd, e))? {
+            SockAddr::Inet(_) => Socket::Tcp(unsafe { TcpListener::from_raw_fd(fd) }),
+            SockAddr::Unix(_) => Socket::Unix(unsafe { UnixListener::from_raw_fd(fd) }),
+            _ => Err(ListenError::UnsupportedSocket(fd))?,
+        };
+
+        let name = names.get(index).unwrap_or(&"").to_string();
+
+        sockets.push((name, socket));
+    }
+
+    Ok(sockets)
+}
+
+/// SO_TYPE of the socket, read with libc since the getsockopt of nix zero initializes an enum
+fn socket_type(fd: RawFd) -> nix::Result<libc::c_int> {
+    let mut socket_type: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+    let res = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            &mut socket_type as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    Errno::result(res).map(|_| socket_type)
+}
+
+/// Open the sockets of the listeners. Systemd listeners take their sockets out of `systemd`, so
+/// each socket is only used once
+pub fn open(
+    listeners: &[ListenerConfig],
+    systemd: &mut Vec<(String, Socket)>,
+) -> Result<Vec<Socket>, ListenError> {
+    let mut sockets = vec![];
+
+    for listener in listeners {
+        match &listener.bind {
+            Bind::Tcp { address } => {
+                let socket = TcpListener::bind(address.as_str())
+                    .map_err(|e| ListenError::BindError(address.clone(), e))?;
+
+                sockets.push(Socket::Tcp(socket));
+            }
+            Bind::Unix { path } => {
+                let is_socket = fs::symlink_metadata(path)
+                    .map(|metadata| metadata.file_type().is_socket())
+                    .unwrap_or(false);
+
+                // a socket left behind by a previous run would fail the bind, but one that still
+                // accepts connections belongs to a server that's running
+                if is_socket {
+                    if UnixStream::connect(path).is_ok() {
+                        Err(ListenError::BindError(
+                            path.clone(),
+                            io::Error::from(io::ErrorKind::AddrInUse),
+                        ))?;
+                    }
+
+                    fs::remove_file(path).map_err(|e| ListenError::BindError(path.clone(), e))?;
+                }
+
+                let socket = UnixListener::bind(path)
+                    .map_err(|e| ListenError::BindError(path.clone(), e))?;
+
+                sockets.push(Socket::Unix(socket));
+            }
+            Bind::Systemd { name } => {
+                let (matching, rest): (Vec<_>, Vec<_>) =
+                    systemd.drain(..).partition(|(socket_name, _)| match name {
+                        Some(name) => socket_name == name,
+                        None => true,
+                    });
+
+                *systemd = rest;
+
+                if matching.is_empty() {
+                    match name {
+                        Some(name) => Err(ListenError::NoNamedSockets(name.clone()))?,
+                        None => Err(ListenError::NoSockets)?,
+                    }
+                }
+
+                sockets.extend(matching.into_iter().map(|(_, socket)| socket));
+            }
+        }
+    }
+
+    Ok(sockets)
+}
+
+/// Port of the first TCP listener that isn't internal
+pub fn public_port(listeners: &[ListenerConfig]) -> Option<u16> {
+    listeners
+        .iter()
+        .filter(|listener| !listener.internal)
+        .filter_map(|listener| listener.bind.tcp_host_port())
+        .map(|(_, port)| port)
+        .next()
+}
+
+/// Where the redirect to HTTPS is served, the port on the host of every TCP listener that isn't
+/// internal
+pub fn redirect_addresses(listeners: &[ListenerConfig], port: &str) -> Vec<String> {
+    let mut addresses: Vec<String> = vec![];
+
+    for listener in listeners.iter().filter(|listener| !listener.internal) {
+        if let Some((host, _)) = listener.bind.tcp_host_port() {
+            let address = format!("{}:{}", host, port);
+
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+    }
+
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(bind: Bind, internal: bool) -> ListenerConfig {
+        ListenerConfig { bind, internal }
+    }
+
+    fn tcp(address: &str, internal: bool) -> ListenerConfig {
+        let address = address.to_string();
+
+        listener(Bind::Tcp { address }, internal)
+    }
+
+    fn unix(path: &str) -> ListenerConfig {
+        let path = path.to_string();
+
+        listener(Bind::Unix { path }, false)
+    }
+
+    fn systemd(name: Option<&str>) -> ListenerConfig {
+        let name = name.map(|name| name.to_string());
+
+        listener(Bind::Systemd { name }, false)
+    }
+
+    /// A socket as if it was passed by systemd with the name
+    fn passed(name: &str) -> (String, Socket) {
+        let socket = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        (name.to_string(), Socket::Tcp(socket))
+    }
+
+    #[test]
+    fn public_port_skips_internal_listeners() {
+        let listeners = vec![
+            unix("/tmp/fn.sock"),
+            tcp("127.0.0.1:9090", true),
+            tcp("[::1]:8443", false),
+            tcp("127.0.0.1:443", false),
+        ];
+
+        assert_eq!(public_port(&listeners), Some(8443));
+        assert_eq!(public_port(&listeners[..2]), None);
+    }
+
+    #[test]
+    fn redirects_on_the_hosts_of_public_tcp_listeners() {
+        let listeners = vec![
+            tcp("127.0.0.1:8443", false),
+            tcp("[::1]:8443", false),
+            tcp("127.0.0.1:9443", false),
+            tcp("10.0.0.1:9090", true),
+            unix("/tmp/fn.sock"),
+            systemd(None),
+        ];
+
+        assert_eq!(
+            redirect_addresses(&listeners, "8080"),
+            vec!["127.0.0.1:8080", "[::1]:8080"]
+        );
+        assert!(redirect_addresses(&listeners[3..], "8080").is_empty());
+    }
+
+    #[test]
+    fn systemd_sockets_are_taken_by_name() {
+        let mut sockets = vec![passed("web"), passed("admin"), passed("web")];
+
+        let web = open(&[systemd(Some("web"))], &mut sockets).unwrap();
+
+        assert_eq!(web.len(), 2);
+        assert_eq!(sockets.len(), 1);
+        assert_eq!(sockets[0].0, "admin");
+
+        match open(&[systemd(Some("web"))], &mut sockets) {
+            Err(ListenError::NoNamedSockets(name)) => assert_eq!(name, "web"),
+            _ => panic!("the sockets named web were already taken"),
+        }
+
+        assert_eq!(open(&[systemd(None)], &mut sockets).unwrap().len(), 1);
+
+        match open(&[systemd(None)], &mut sockets) {
+            Err(ListenError::NoSockets) => {}
+            _ => panic!("every socket was already taken"),
+        }
+    }
+
+    #[test]
+    fn replaces_a_stale_unix_socket_but_not_one_in_use() {
+        let path = env::temp_dir().join(format!("fn-listen-{}.sock", process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let listeners = [unix(path)];
+
+        // the socket is left behind once the listener is dropped
+        drop(open(&listeners, &mut vec![]).unwrap());
+        let in_use = open(&listeners, &mut vec![]).unwrap();
+
+        match open(&listeners, &mut vec![]) {
+            Err(ListenError::BindError(address, e)) => {
+                assert_eq!(address, path);
+                assert_eq!(e.kind(), io::ErrorKind::AddrInUse);
+            }
+            _ => panic!("the socket is still in use"),
+        }
+
+        drop(in_use);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,11 +1,19 @@
+mod commands;
 mod health;
+mod listen;
+mod metrics;
+mod serve;
+mod tls;
 
-use actix_web::{middleware, web, App, HttpServer};
-
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use failure::Fail;
 
-use fn_core::config::{Config, ConfigError};
-use fn_gateway::bootstrap_gateway;
+use fn_core::config::{Bind, Config, ConfigError, ListenerConfig};
+use fn_core::secrets::SecretError;
+use fn_core::tracing::TracingError;
+
+use crate::listen::ListenError;
+use crate::tls::TlsError;
 
 #[derive(Debug, Fail)]
 pub enum Errors {
@@ -17,37 +25,245 @@ pub enum Errors {
 
     #[fail(display = "Invalid config {}", _0)]
     InvalidConfig(&'static str),
+
+    #[fail(display = "Failed to create the secrets provider {}", _0)]
+    SecretsError(SecretError),
+
+    #[fail(display = "Failed to start tracing {}", _0)]
+    TracingError(TracingError),
+
+    #[fail(display = "Failed to load the TLS config {}", _0)]
+    TlsError(TlsError),
+
+    #[fail(display = "Failed to open the listeners {}", _0)]
+    ListenError(ListenError),
+
+    #[fail(display = "Invalid value for --{}: \"{}\"", _0, _1)]
+    InvalidArgument(&'static str, String),
+
+    #[fail(display = "Function {} does not exist", _0)]
+    UnknownFunction(String),
+
+    #[fail(
+        display = "--path is required to invoke route {}, which has parameters",
+        _0
+    )]
+    PathRequired(String),
+
+    #[fail(display = "Failed to invoke the function {}", _0)]
+    InvokeError(failure::Error),
+
+    #[fail(display = "Failed to access {}: {}", _0, _1)]
+    FileError(String, std::io::Error),
+}
+
+fn cli<'a, 'b>() -> App<'a, 'b> {
+    let config = Arg::with_name("config")
+        .long("config")
+        .short("c")
+        .value_name("FILE")
+        .help("Config file to load, config.yaml by default")
+        .takes_value(true)
+        .global(true);
+
+    App::new("pod")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Serves functions over HTTP, starts the server when no command is given")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(config)
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Start the server")
+                .arg(
+                    Arg::with_name("host")
+                        .long("host")
+                        .takes_value(true)
+                        .help("Overrides networking.host, used when there are no listeners"),
+                )
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .takes_value(true)
+                        .help("Overrides networking.port, used when there are no listeners"),
+                )
+                .arg(
+                    Arg::with_name("workers")
+                        .long("workers")
+                        .takes_value(true)
+                        .help("Overrides networking.workers"),
+                )
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .value_name("ADDRESS")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help(
+                            "Listen on a TCP address, or a unix socket with unix:PATH. Replaces \
+                             the listeners that aren't internal, can be repeated",
+                        ),
+                ),
+        )
+        .subcommand(SubCommand::with_name("validate").about("Check the config for problems"))
+        .subcommand(SubCommand::with_name("routes").about("Print the route table"))
+        .subcommand(
+            SubCommand::with_name("invoke")
+                .about("Run a function once, without the server")
+                .arg(
+                    Arg::with_name("function")
+                        .required(true)
+                        .help("Name of the function"),
+                )
+                .arg(
+                    Arg::with_name("method")
+                        .long("method")
+                        .short("X")
+                        .takes_value(true)
+                        .default_value("GET"),
+                )
+                .arg(Arg::with_name("path").long("path").takes_value(true).help(
+                    "Path and query string, the route by default unless it has \
+                             parameters",
+                ))
+                .arg(
+                    Arg::with_name("body")
+                        .long("body")
+                        .short("d")
+                        .takes_value(true)
+                        .help("Body of the request"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("secrets")
+                .about("Manage the secrets of the encrypted_file provider")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("generate-key")
+                        .about("Print a new random key, to be saved as the key_file"),
+                )
+                .subcommand(
+                    SubCommand::with_name("encrypt")
+                        .about("Encrypt a YAML map of secret names to values")
+                        .arg(
+                            Arg::with_name("input")
+                                .required(true)
+                                .help("YAML file with the secrets"),
+                        )
+                        .arg(
+                            Arg::with_name("key-file")
+                                .long("key-file")
+                                .short("k")
+                                .takes_value(true)
+                                .required(true)
+                                .help("File with the key, as printed by generate-key"),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .short("o")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Where to write the encrypted file, the provider's path"),
+                        ),
+                ),
+        )
+        .subcommand(SubCommand::with_name("version").about("Print the version"))
 }
 
 fn main() -> Result<(), Errors> {
-    let config = Config::load().map_err(|source| Errors::ConfigLoadError(source))?;
-    let address = config.address();
+    // info by default, RUST_LOG overrides it
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let matches = cli().get_matches();
+
+    match matches.subcommand() {
+        ("serve", Some(args)) => {
+            let mut config = load_config(&matches, Some(args))?;
+            override_networking(&mut config, args)?;
+            commands::validate(&config)?;
+            serve::serve(config)
+        }
+        ("validate", Some(args)) => {
+            let config = load_config(&matches, Some(args))?;
+            commands::validate(&config)?;
+
+            println!("{} is valid", config.path().display());
+            Ok(())
+        }
+        ("routes", Some(args)) => {
+            commands::routes(&load_config(&matches, Some(args))?);
+            Ok(())
+        }
+        ("invoke", Some(args)) => {
+            let config = load_config(&matches, Some(args))?;
+            commands::validate(&config)?;
+            commands::invoke(&config, args)
+        }
+        ("secrets", Some(args)) => match args.subcommand() {
+            ("generate-key", Some(_)) => commands::generate_key(),
+            ("encrypt", Some(args)) => commands::encrypt_secrets(args),
+            _ => Ok(()),
+        },
+        ("version", Some(_)) => {
+            println!("pod {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        _ => {
+            let config = load_config(&matches, None)?;
+            commands::validate(&config)?;
+            serve::serve(config)
+        }
+    }
+}
+
+/// Load the config from --config, which may be given before or after the command
+fn load_config(matches: &ArgMatches, args: Option<&ArgMatches>) -> Result<Config, Errors> {
+    let path = args
+        .and_then(|args| args.value_of("config"))
+        .or_else(|| matches.value_of("config"))
+        .unwrap_or(Config::DEFAULT_PATH);
+
+    Config::load_from(path).map_err(|source| Errors::ConfigLoadError(source))
+}
+
+fn override_networking(config: &mut Config, args: &ArgMatches) -> Result<(), Errors> {
+    let networking = config.networking_mut();
+
+    if let Some(host) = args.value_of("host") {
+        networking.host = host.to_string();
+    }
+
+    if let Some(port) = args.value_of("port") {
+        networking.port = port.to_string();
+    }
+
+    if let Some(workers) = args.value_of("workers") {
+        networking.workers = workers
+            .parse()
+            .map_err(|_| Errors::InvalidArgument("workers", workers.to_string()))?;
+    }
+
+    if let Some(addresses) = args.values_of("listen") {
+        networking.listeners.retain(|listener| listener.internal);
+
+        for address in addresses {
+            let bind = if address.starts_with("unix:") {
+                Bind::Unix {
+                    path: address["unix:".len()..].to_string(),
+                }
+            } else {
+                Bind::Tcp {
+                    address: address.to_string(),
+                }
+            };
 
-    if config.functions().len() == 0 {
-        return Err(Errors::InvalidConfig(
-            "Config must contain at least 1 function",
-        ));
+            networking.listeners.push(ListenerConfig {
+                bind,
+                internal: false,
+            });
+        }
     }
 
-    HttpServer::new(move || {
-        // registering the data here allows for each thread to have their own function runtime cache
-        // this is particularly useful when using unix sockets, since each thread will create their
-        // own function process
-        let app_data = web::Data::new(fn_core::state::State::new());
-
-        let mut app = App::new()
-            .wrap(middleware::Logger::default())
-            .register_data(app_data.clone())
-            .route("/_ah", web::get().to(health::handle));
-
-        // this will panic if something goes wrong during the bootstrapping process
-        app = bootstrap_gateway(app, &config);
-
-        app
-    })
-    .workers(1)
-    .bind(address)
-    .map_err(|e| Errors::WebError(e))?
-    .run()
-    .map_err(|e| Errors::WebError(e))
+    Ok(())
 }
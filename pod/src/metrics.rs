@@ -0,0 +1,16 @@
+use actix_web::{web, HttpResponse};
+use fn_core::config::Config;
+use fn_core::metrics;
+use fn_core::state::WorkerStates;
+
+/// Metrics of the pod and its functions in the Prometheus text format
+pub fn handle(workers: web::Data<WorkerStates>, config: web::Data<Config>) -> HttpResponse {
+    metrics::record_resource_usage(&workers.states(), config.functions());
+
+    match metrics::encode() {
+        Ok((content_type, body)) => HttpResponse::Ok().content_type(content_type).body(body),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Failed to encode metrics {}", e))
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use actix_http::{Request, Response};
+use actix_service::{IntoNewService, NewService};
+use actix_web::dev::MessageBody;
+use actix_web::{middleware, web, App, Error, HttpRequest, HttpServer};
+use fn_core::config::{Config, ListenerConfig, RuntimeStore};
+use fn_core::secrets::SecretProvider;
+use fn_core::state::{AppData, State, WorkerStates};
+use fn_core::tracing;
+use fn_gateway::{bootstrap_admin, bootstrap_gateway};
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::listen::{self, Socket};
+use crate::{health, metrics, tls, Errors};
+
+thread_local! {
+    /// The runtimes of the worker running on the thread, when each worker has its own
+    static WORKER_STATE: RefCell<Option<AppData>> = RefCell::new(None);
+}
+
+/// Accept connections from the sockets, TCP sockets are served over TLS when it's configured
+fn listen_on<F, I, S, B>(
+    mut server: HttpServer<F, I, S, B>,
+    sockets: Vec<Socket>,
+    tls_config: &Option<rustls::ServerConfig>,
+) -> Result<HttpServer<F, I, S, B>, Errors>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoNewService<S>,
+    S: NewService<Config = actix_server_config::ServerConfig, Request = Request>,
+    S::Error: Into<Error>,
+    S::InitError: fmt::Debug,
+    S::Response: Into<Response<B>>,
+    S::Service: 'static,
+    B: MessageBody + 'static,
+{
+    for socket in sockets {
+        server = match (socket, tls_config) {
+            (Socket::Tcp(listener), Some(tls_config)) => {
+                server.listen_rustls(listener, tls_config.clone())
+            }
+            (Socket::Tcp(listener), None) => server.listen(listener),
+            (Socket::Unix(listener), _) => server.listen_uds(listener),
+        }
+        .map_err(|e| Errors::WebError(e))?;
+    }
+
+    Ok(server)
+}
+
+/// Run the gateway until it's stopped, the config is expected to have been validated
+pub fn serve(config: Config) -> Result<(), Errors> {
+    let secrets: Arc<dyn SecretProvider> = Arc::from(
+        config
+            .secrets()
+            .provider()
+            .map_err(|e| Errors::SecretsError(e))?,
+    );
+
+    tracing::init(config.tracing()).map_err(|e| Errors::TracingError(e))?;
+
+    // the admin API is only served when it has a token
+    let admin_token = match config.admin().token.clone() {
+        Some(mut token) => {
+            token
+                .resolve(secrets.as_ref())
+                .map_err(|e| Errors::SecretsError(e))?;
+
+            Some(token.value().to_string())
+        }
+        None => None,
+    };
+
+    let workers = config.networking().workers;
+
+    let tls_config = match &config.networking().tls {
+        Some(tls) => Some(tls::server_config(tls).map_err(|e| Errors::TlsError(e))?),
+        None => None,
+    };
+    let (internal, public): (Vec<ListenerConfig>, Vec<ListenerConfig>) = config
+        .networking()
+        .listeners()
+        .into_iter()
+        .partition(|listener| listener.internal);
+
+    let https_port = listen::public_port(&public).unwrap_or(443);
+    let redirect_addresses = match config.networking().tls.as_ref() {
+        Some(tls) => match &tls.redirect_port {
+            Some(port) => listen::redirect_addresses(&public, port),
+            None => vec![],
+        },
+        None => vec![],
+    };
+
+    // the sockets are opened before any of the servers start, so that a listener that can't be
+    // opened stops the startup
+    let mut systemd = listen::systemd_sockets().map_err(|e| Errors::ListenError(e))?;
+    let public_sockets = listen::open(&public, &mut systemd).map_err(|e| Errors::ListenError(e))?;
+    let internal_sockets =
+        listen::open(&internal, &mut systemd).map_err(|e| Errors::ListenError(e))?;
+
+    // the admin API and metrics move to the internal listeners when there are any
+    let has_internal = !internal.is_empty();
+
+    // a single cache of runtimes for all of the workers, rather than one per worker
+    let shared = match config.gateway().runtime_store {
+        RuntimeStore::Shared => Some(web::Data::new(State::new(secrets.clone()))),
+        RuntimeStore::PerWorker => None,
+    };
+    let health_checks_started = Arc::new(AtomicBool::new(false));
+
+    let worker_states = web::Data::new(WorkerStates::new(match &shared {
+        Some(_) => 1,
+        None => workers,
+    }));
+
+    if let Some(shared) = &shared {
+        worker_states.register(shared.clone());
+    }
+
+    let internal_server = {
+        let config = config.clone();
+        let admin_token = admin_token.clone();
+        let worker_states = worker_states.clone();
+        // validation requires shared runtimes with internal listeners
+        let app_data = shared
+            .clone()
+            .unwrap_or_else(|| web::Data::new(State::new(secrets.clone())));
+
+        HttpServer::new(move || {
+            let mut app = App::new()
+                .wrap(middleware::Logger::default())
+                .register_data(app_data.clone())
+                .register_data(worker_states.clone())
+                .data(config.clone())
+                .route("/_ah", web::get().to(health::live))
+                .route("/_ah/live", web::get().to(health::live))
+                .route("/_ah/ready", web::get().to(health::ready))
+                .route("/metrics", web::get().to(metrics::handle));
+
+            if let Some(token) = &admin_token {
+                app = bootstrap_admin(app, &config, token.clone());
+            }
+
+            app
+        })
+        .workers(1)
+    };
+
+    let server = HttpServer::new(move || {
+        // with per worker runtimes, creating the data here gives each worker its own function
+        // runtime cache, so each worker creates its own process for every unix socket function.
+        // The app is built on the worker's thread once per public socket, the worker keeps the
+        // data it created first
+        let (app_data, created) = match &shared {
+            Some(shared) => (
+                shared.clone(),
+                !health_checks_started.swap(true, Ordering::SeqCst),
+            ),
+            None => WORKER_STATE.with(|worker_state| {
+                let mut worker_state = worker_state.borrow_mut();
+
+                match &*worker_state {
+                    Some(app_data) => (app_data.clone(), false),
+                    None => {
+                        let app_data = web::Data::new(State::new(secrets.clone()));
+                        worker_states.register(app_data.clone());
+                        *worker_state = Some(app_data.clone());
+                        (app_data, true)
+                    }
+                }
+            }),
+        };
+
+        // each cache of runtimes is warmed up and checked once
+        if created {
+            fn_gateway::start_health_checks(app_data.clone(), &config);
+        }
+
+        let mut app = App::new()
+            .wrap(middleware::Logger::default())
+            .register_data(app_data.clone())
+            .register_data(worker_states.clone())
+            .data(config.clone())
+            .route("/_ah", web::get().to(health::live))
+            .route("/_ah/live", web::get().to(health::live))
+            .route("/_ah/ready", web::get().to(health::ready));
+
+        if !has_internal {
+            app = app.route("/metrics", web::get().to(metrics::handle));
+
+            if let Some(token) = &admin_token {
+                app = bootstrap_admin(app, &config, token.clone());
+            }
+        }
+
+        // the config has already been validated, so bootstrapping the routes won't panic
+        app = bootstrap_gateway(app, &config);
+
+        app
+    })
+    .workers(workers);
+
+    let server = listen_on(server, public_sockets, &tls_config)?;
+
+    let system = actix_rt::System::new("pod");
+
+    server.start();
+
+    if has_internal {
+        listen_on(internal_server, internal_sockets, &tls_config)?.start();
+    }
+
+    // plain HTTP is only served to redirect it to HTTPS
+    if !redirect_addresses.is_empty() {
+        let mut redirect_server = HttpServer::new(move || {
+            App::new()
+                .wrap(middleware::Logger::default())
+                .default_service(
+                    web::route().to(move |req: HttpRequest| tls::redirect(&req, https_port)),
+                )
+        })
+        .workers(1);
+
+        for address in redirect_addresses {
+            redirect_server = redirect_server
+                .bind(address)
+                .map_err(|e| Errors::WebError(e))?;
+        }
+
+        redirect_server.start();
+    }
+
+    system.run().map_err(|e| Errors::WebError(e))
+}
@@ -0,0 +1,334 @@
+use actix_web::{HttpRequest, HttpResponse};
+use failure::Fail;
+use fn_core::config::{TlsConfig, TlsVersion};
+use log::{info, warn};
+use rustls::internal::pemfile;
+use rustls::sign::{self, CertifiedKey, SigningKey};
+use rustls::{
+    AllowAnyAuthenticatedClient, Certificate, NoClientAuth, ProtocolVersion, ResolvesServerCert,
+    RootCertStore, ServerConfig, SignatureScheme,
+};
+use std::fs;
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Fail)]
+pub enum TlsError {
+    #[fail(display = "Failed to read {}: {}", _0, _1)]
+    ReadError(String, std::io::Error),
+
+    #[fail(display = "No certificates found in {}", _0)]
+    NoCertificates(String),
+
+    #[fail(display = "No PKCS#8 or RSA private key found in {}", _0)]
+    NoPrivateKey(String),
+
+    #[fail(display = "Unsupported private key in {}", _0)]
+    UnsupportedKey(String),
+
+    #[fail(
+        display = "The private key in {} doesn't match the certificate in {}",
+        _0, _1
+    )]
+    KeyMismatch(String, String),
+
+    #[fail(display = "Invalid certificate authorities in {}", _0)]
+    InvalidClientCa(String),
+}
+
+/// Build the rustls config of the server. The certificate is served by a resolver that reloads it
+/// when its files change, so open connections keep the certificate they were established with and
+/// new connections get the new one
+pub fn server_config(tls: &TlsConfig) -> Result<ServerConfig, TlsError> {
+    let verifier = match &tls.client_ca {
+        Some(path) => {
+            let file = fs::File::open(path).map_err(|e| TlsError::ReadError(path.clone(), e))?;
+
+            let mut roots = RootCertStore::empty();
+
+            match roots.add_pem_file(&mut BufReader::new(file)) {
+                Ok((valid, _)) if valid > 0 => {}
+                _ => Err(TlsError::InvalidClientCa(path.clone()))?,
+            }
+
+            AllowAnyAuthenticatedClient::new(roots)
+        }
+        None => NoClientAuth::new(),
+    };
+
+    let mut config = ServerConfig::new(verifier);
+
+    config.versions = match tls.min_version {
+        TlsVersion::Tls12 => vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2],
+        TlsVersion::Tls13 => vec![ProtocolVersion::TLSv1_3],
+    };
+    config.cert_resolver = ReloadingResolver::start(tls)?;
+
+    Ok(config)
+}
+
+/// Serves the certificate and key, checking their files for changes in the background
+struct ReloadingResolver {
+    cert: String,
+    key: String,
+    certified_key: RwLock<CertifiedKey>,
+}
+
+impl ReloadingResolver {
+    fn start(tls: &TlsConfig) -> Result<Arc<ReloadingResolver>, TlsError> {
+        let resolver = Arc::new(ReloadingResolver {
+            cert: tls.cert.clone(),
+            key: tls.key.clone(),
+            certified_key: RwLock::new(load_certified_key(&tls.cert, &tls.key)?),
+        });
+
+        let watched = resolver.clone();
+        let interval = Duration::from_secs(tls.reload_interval_secs);
+
+        thread::spawn(move || {
+            let mut modified = watched.modified();
+
+            loop {
+                thread::sleep(interval);
+
+                let current = watched.modified();
+
+                if current == modified {
+                    continue;
+                }
+
+                // a renewal may write the certificate and key separately, a mismatched pair fails
+                // to load and is tried again, as the files are still seen as changed, until both
+                // have been written
+                match load_certified_key(&watched.cert, &watched.key) {
+                    Ok(certified_key) => {
+                        *watched.certified_key.write().unwrap() = certified_key;
+                        modified = current;
+
+                        info!("Reloaded the TLS certificate from {}", watched.cert);
+                    }
+                    Err(e) => warn!("Failed to reload the TLS certificate: {}", e),
+                }
+            }
+        });
+
+        Ok(resolver)
+    }
+
+    /// When the certificate and key were last modified, none if either can't be read
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        Some((modified(&self.cert)?, modified(&self.key)?))
+    }
+}
+
+impl ResolvesServerCert for ReloadingResolver {
+    fn resolve(
+        &self,
+        _server_name: Option<webpki::DNSNameRef>,
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<CertifiedKey> {
+        self.certified_key.read().ok().map(|key| key.clone())
+    }
+}
+
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey, TlsError> {
+    let cert_pem =
+        fs::read(cert_path).map_err(|e| TlsError::ReadError(cert_path.to_string(), e))?;
+    let key_pem = fs::read(key_path).map_err(|e| TlsError::ReadError(key_path.to_string(), e))?;
+
+    let certs = pemfile::certs(&mut &cert_pem[..]).unwrap_or_default();
+
+    if certs.is_empty() {
+        Err(TlsError::NoCertificates(cert_path.to_string()))?;
+    }
+
+    let mut keys = pemfile::pkcs8_private_keys(&mut &key_pem[..]).unwrap_or_default();
+
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut &key_pem[..]).unwrap_or_default();
+    }
+
+    let key = keys
+        .first()
+        .ok_or_else(|| TlsError::NoPrivateKey(key_path.to_string()))?;
+
+    let signing_key = sign::any_supported_type(key)
+        .map_err(|_| TlsError::UnsupportedKey(key_path.to_string()))?;
+
+    if !is_key_of(&certs[0], signing_key.as_ref()) {
+        Err(TlsError::KeyMismatch(
+            key_path.to_string(),
+            cert_path.to_string(),
+        ))?;
+    }
+
+    Ok(CertifiedKey::new(certs, Arc::new(signing_key)))
+}
+
+/// Whether the key is the private key of the certificate, a message signed with the key has to be
+/// verified by the certificate
+fn is_key_of(cert: &Certificate, key: &dyn SigningKey) -> bool {
+    let message = b"fn-rs key check";
+    let schemes = [
+        (
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            &webpki::ECDSA_P256_SHA256,
+        ),
+        (
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            &webpki::ECDSA_P384_SHA384,
+        ),
+        (SignatureScheme::ED25519, &webpki::ED25519),
+        (
+            SignatureScheme::RSA_PKCS1_SHA256,
+            &webpki::RSA_PKCS1_2048_8192_SHA256,
+        ),
+    ];
+    let offered: Vec<SignatureScheme> = schemes.iter().map(|(scheme, _)| *scheme).collect();
+
+    let signer = match key.choose_scheme(&offered) {
+        Some(signer) => signer,
+        None => return false,
+    };
+
+    let algorithm = match schemes
+        .iter()
+        .find(|(scheme, _)| *scheme == signer.get_scheme())
+    {
+        Some((_, algorithm)) => algorithm,
+        None => return false,
+    };
+
+    let signature = match signer.sign(message) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    webpki::EndEntityCert::from(untrusted::Input::from(&cert.0))
+        .and_then(|cert| {
+            cert.verify_signature(
+                algorithm,
+                untrusted::Input::from(message),
+                untrusted::Input::from(&signature),
+            )
+        })
+        .is_ok()
+}
+
+/// Redirect a plain HTTP request to the same URL over HTTPS, on the port of the server. A 308 is
+/// used so that the method and body are kept
+pub fn redirect(req: &HttpRequest, https_port: u16) -> HttpResponse {
+    let info = req.connection_info();
+    let host = strip_port(info.host());
+
+    let location = match https_port {
+        443 => format!("https://{}{}", host, req.uri()),
+        port => format!("https://{}:{}{}", host, port, req.uri()),
+    };
+
+    HttpResponse::PermanentRedirect()
+        .header("location", location)
+        .finish()
+}
+
+/// The host without its port, an IPv6 address keeps its brackets
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(index) if !host[index..].contains(']') => &host[..index],
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    /// Path of a file in tests/fixtures/tls
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn location(host: &str, uri: &str, https_port: u16) -> String {
+        let req = TestRequest::with_uri(uri)
+            .header("host", host)
+            .to_http_request();
+        let res = redirect(&req, https_port);
+
+        assert_eq!(res.status().as_u16(), 308);
+
+        res.headers()["location"].to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn redirects_to_https() {
+        assert_eq!(
+            location("example.com", "/a?b=c", 443),
+            "https://example.com/a?b=c"
+        );
+        assert_eq!(
+            location("example.com:8080", "/a", 8443),
+            "https://example.com:8443/a"
+        );
+        assert_eq!(location("[::1]:8080", "/", 443), "https://[::1]/");
+        assert_eq!(location("[::1]", "/", 8443), "https://[::1]:8443/");
+    }
+
+    #[test]
+    fn strips_the_port() {
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("127.0.0.1:80"), "127.0.0.1");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[2001:db8::1]:443"), "[2001:db8::1]");
+    }
+
+    #[test]
+    fn loads_matching_keys() {
+        for name in &["ec", "rsa"] {
+            let cert = fixture(&format!("{}.crt", name));
+            let key = fixture(&format!("{}.key", name));
+
+            assert!(load_certified_key(&cert, &key).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_a_key_of_another_certificate() {
+        let cert = fixture("ec.crt");
+
+        for key in &[fixture("other.key"), fixture("rsa.key")] {
+            match load_certified_key(&cert, key) {
+                Err(TlsError::KeyMismatch(..)) => {}
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("{} was loaded with {}", key, cert),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_files_without_a_certificate_or_key() {
+        let cert = fixture("ec.crt");
+        let key = fixture("ec.key");
+
+        match load_certified_key(&key, &key) {
+            Err(TlsError::NoCertificates(path)) => assert_eq!(path, key),
+            _ => panic!("the key was loaded as a certificate"),
+        }
+
+        match load_certified_key(&cert, &cert) {
+            Err(TlsError::NoPrivateKey(path)) => assert_eq!(path, cert),
+            _ => panic!("the certificate was loaded as a key"),
+        }
+
+        match load_certified_key(&fixture("missing.crt"), &key) {
+            Err(TlsError::ReadError(..)) => {}
+            _ => panic!("a missing certificate was loaded"),
+        }
+    }
+}